@@ -0,0 +1,85 @@
+use crate::{infallible_rollback, ExecutionOrder, RollbackGuard, RollbackStack};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Acquires a resource for every item of `items`, all or nothing.
+///
+/// `acquire` is called for each item in order. If all acquisitions succeed, the acquired
+/// resources are returned together with a guard that calls `release` on each of them in reverse
+/// order of acquisition when it is rolled back.
+///
+/// If an acquisition fails, all resources that were already acquired are released in reverse
+/// order and the error is returned. No further items are acquired in this case. The same
+/// happens if `acquire` panics. If `release` panics, the remaining resources are still
+/// released.
+///
+/// The resources are returned as an [`Rc`] slice that is shared with the guard, so they do not
+/// need to implement [`Clone`] and are released exactly once, by reference.
+///
+/// ```rust
+/// # use std::cell::RefCell;
+/// use transaction_rollback::acquire_all;
+///
+/// let released = RefCell::new(Vec::new());
+///
+/// let result = acquire_all(
+///     [1, 2, 3, 4],
+///     |n| if n == 3 { Err("can not acquire 3") } else { Ok(n * 10) },
+///     |r| released.borrow_mut().push(*r),
+/// );
+/// assert_eq!(Some("can not acquire 3"), result.err());
+/// assert_eq!(vec![20, 10], *released.borrow());
+///
+/// released.borrow_mut().clear();
+///
+/// let (resources, guard) = acquire_all(
+///     [1, 2, 3],
+///     |n| Ok::<_, ()>(n * 10),
+///     |r| released.borrow_mut().push(*r),
+/// )
+/// .unwrap();
+/// assert_eq!([10, 20, 30], *resources);
+/// drop(guard);
+/// assert_eq!(vec![30, 20, 10], *released.borrow());
+/// ```
+#[allow(clippy::type_complexity)]
+pub fn acquire_all<'a, I, R, E, A, F>(
+    items: I,
+    mut acquire: A,
+    release: F,
+) -> Result<(Rc<[R]>, RollbackGuard<'a, (), ()>), E>
+where
+    I: IntoIterator,
+    A: FnMut(I::Item) -> Result<R, E>,
+    F: Fn(&R) + 'a,
+    R: 'a,
+{
+    let acquired = RefCell::new(Vec::new());
+    // Declared after `acquired`, so that it is dropped (and thus rolled back) first.
+    let mut stack = RollbackStack::new(ExecutionOrder::Lifo);
+
+    for item in items {
+        let resource = acquire(item)?;
+        let index = {
+            let mut acquired = acquired.borrow_mut();
+            acquired.push(resource);
+            acquired.len() - 1
+        };
+        let (acquired, release) = (&acquired, &release);
+        stack.push(infallible_rollback(move || {
+            release(&acquired.borrow()[index])
+        }));
+    }
+
+    stack.ok();
+    let resources: Rc<[R]> = acquired.into_inner().into();
+    let shared = resources.clone();
+    let guard = infallible_rollback(move || {
+        // A stack again, so that the remaining resources are released if `release` panics.
+        let mut stack = RollbackStack::new(ExecutionOrder::Lifo);
+        for resource in shared.iter() {
+            stack.push(infallible_rollback(|| release(resource)));
+        }
+    });
+    Ok((resources, guard))
+}
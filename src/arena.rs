@@ -0,0 +1,220 @@
+use crate::rollback::private;
+use crate::{collapse_results, MaybeError, Rollback, RollbackGuard};
+use std::collections::BinaryHeap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::mem;
+
+/// A collection of [`RollbackGuard`]s that are rolled back in an order derived from declared
+/// dependencies.
+///
+/// Each guard is added via [`RollbackArena::register`], which returns its [`ArenaId`].
+/// [`RollbackArena::add_dependency`] declares that one guard must be torn down before another.
+/// When the arena is dropped or rolled back via [`Rollback::do_rollback`], the guards are
+/// rolled back in an order that satisfies all dependencies. This can express the teardown of
+/// graphs of interdependent resources, which the fixed order of a [`crate::RollbackStack`]
+/// can not always express.
+///
+/// Guards that do not depend on each other are rolled back in reverse registration order, like
+/// in a stack with [`crate::ExecutionOrder::Lifo`]. Dependencies that would form a cycle are
+/// rejected by `add_dependency`, so a valid order always exists. When the arena is dropped, each
+/// guard is dropped individually and handles its failure on its own;
+/// [`Rollback::do_rollback`] rolls back all guards, even if some fail, and collects their
+/// results in execution order.
+///
+/// ```rust
+/// # use std::cell::RefCell;
+/// use transaction_rollback::{infallible_rollback, RollbackArena};
+///
+/// let calls = &RefCell::new(Vec::new());
+/// let call = |name| infallible_rollback(move || calls.borrow_mut().push(name));
+///
+/// let mut arena = RollbackArena::new();
+/// let connection = arena.register(call("close connection"));
+/// let session = arena.register(call("end session"));
+/// let file = arena.register(call("delete file"));
+/// // The file is uploaded via the session, which uses the connection.
+/// arena.add_dependency(session, connection).unwrap();
+/// arena.add_dependency(file, session).unwrap();
+/// // A cycle is rejected.
+/// assert!(arena.add_dependency(connection, file).is_err());
+///
+/// drop(arena);
+/// assert_eq!(
+///     vec!["delete file", "end session", "close connection"],
+///     *calls.borrow()
+/// );
+/// ```
+pub struct RollbackArena<'a, T, E>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    guards: Vec<RollbackGuard<'a, T, E>>,
+    // For each guard, the guards that must be torn down after it.
+    successors: Vec<Vec<usize>>,
+}
+
+/// The id of a guard in a [`RollbackArena`], returned by [`RollbackArena::register`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ArenaId(usize);
+
+impl<'a, T, E> RollbackArena<'a, T, E>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    /// Creates a new, empty arena.
+    pub fn new() -> Self {
+        Self {
+            guards: Vec::new(),
+            successors: Vec::new(),
+        }
+    }
+
+    /// Adds a guard to the arena and returns its id, to declare dependencies with.
+    pub fn register(&mut self, guard: RollbackGuard<'a, T, E>) -> ArenaId {
+        self.guards.push(guard);
+        self.successors.push(Vec::new());
+        ArenaId(self.guards.len() - 1)
+    }
+
+    /// Declares that the guard `first` must be torn down before the guard `then`.
+    ///
+    /// Returns [`DependencyCycle`] without adding the dependency if it would form a cycle with
+    /// the dependencies declared so far, including if `first` and `then` are the same guard.
+    ///
+    /// # Panics
+    /// If one of the ids was not returned by [`Self::register`] of this arena.
+    pub fn add_dependency(&mut self, first: ArenaId, then: ArenaId) -> Result<(), DependencyCycle> {
+        assert!(
+            first.0 < self.guards.len() && then.0 < self.guards.len(),
+            "transaction-rollback: the id does not belong to this arena."
+        );
+        if self.precedes(then.0, first.0) {
+            return Err(DependencyCycle { first, then });
+        }
+        self.successors[first.0].push(then.0);
+        Ok(())
+    }
+
+    /// The number of guards in the arena.
+    pub fn len(&self) -> usize {
+        self.guards.len()
+    }
+
+    /// Returns `true` if the arena contains no guards.
+    pub fn is_empty(&self) -> bool {
+        self.guards.is_empty()
+    }
+
+    /// Drops the arena but does not run any of the rollbacks, see [`RollbackGuard::ok`].
+    pub fn ok(mut self) {
+        for guard in self.take_ordered() {
+            guard.ok();
+        }
+    }
+
+    /// Returns `true` if the guard `from` is torn down before the guard `to`, or if they are
+    /// the same guard.
+    fn precedes(&self, from: usize, to: usize) -> bool {
+        let mut visited = vec![false; self.guards.len()];
+        let mut pending = vec![from];
+        while let Some(current) = pending.pop() {
+            if current == to {
+                return true;
+            }
+            if !mem::replace(&mut visited[current], true) {
+                pending.extend(&self.successors[current]);
+            }
+        }
+        false
+    }
+
+    /// Removes all guards from the arena, in execution order.
+    fn take_ordered(&mut self) -> Vec<RollbackGuard<'a, T, E>> {
+        let successors = mem::take(&mut self.successors);
+        let mut guards: Vec<_> = mem::take(&mut self.guards).into_iter().map(Some).collect();
+        let mut predecessors = vec![0usize; guards.len()];
+        for &then in successors.iter().flatten() {
+            predecessors[then] += 1;
+        }
+        // A max-heap, so the guard registered last among the ready ones is torn down first.
+        let mut ready: BinaryHeap<_> = (0..guards.len())
+            .filter(|&i| predecessors[i] == 0)
+            .collect();
+        let mut ordered = Vec::with_capacity(guards.len());
+        while let Some(current) = ready.pop() {
+            ordered.extend(guards[current].take());
+            for &then in &successors[current] {
+                predecessors[then] -= 1;
+                if predecessors[then] == 0 {
+                    ready.push(then);
+                }
+            }
+        }
+        ordered
+    }
+}
+
+impl<'a, T, E> Default for RollbackArena<'a, T, E>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T, E> Rollback for RollbackArena<'a, T, E>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    type RollbackOk = Vec<T>;
+    type RollbackError = Vec<E>;
+
+    /// Performs the rollback of all guards in dependency order, consuming the arena.
+    ///
+    /// All guards are rolled back, even if some of them fail. If all of them succeed, their
+    /// results are returned in execution order, otherwise all errors are returned in
+    /// execution order.
+    fn do_rollback(mut self) -> Result<Vec<T>, Vec<E>> {
+        collapse_results(self.take_ordered().into_iter().map(Rollback::do_rollback))
+    }
+}
+
+impl<'a, T, E> Drop for RollbackArena<'a, T, E>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    fn drop(&mut self) {
+        // Each guard handles a failing rollback on its own when dropped.
+        for guard in self.take_ordered() {
+            drop(guard);
+        }
+    }
+}
+
+/// The error of [`RollbackArena::add_dependency`] if the dependency would form a cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DependencyCycle {
+    /// The guard that was to be torn down first.
+    pub first: ArenaId,
+    /// The guard that was to be torn down after `first`.
+    pub then: ArenaId,
+}
+
+impl Display for DependencyCycle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Tearing down {:?} before {:?} would form a dependency cycle",
+            self.first, self.then
+        )
+    }
+}
+
+impl Error for DependencyCycle {}
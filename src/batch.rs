@@ -0,0 +1,64 @@
+use crate::{infallible_rollback, ExecutionOrder, RollbackStack};
+use std::cell::RefCell;
+
+/// Processes all `items`, rolling back every processed item if processing any of them fails.
+///
+/// `process` is called for each item in order. If it succeeds for all items, the outputs are
+/// returned in order. If it fails for an item, `rollback_item` is called for the outputs of all
+/// items processed so far in reverse order, and the error is returned. No further items are
+/// processed in this case. The same happens if `process` panics.
+///
+/// ```rust
+/// # use std::cell::RefCell;
+/// use transaction_rollback::try_process_all;
+///
+/// let rolled_back = RefCell::new(Vec::new());
+///
+/// let result = try_process_all(
+///     ["1", "2", "three", "4"],
+///     |s| s.parse::<u8>(),
+///     |n| rolled_back.borrow_mut().push(*n),
+/// );
+/// assert!(result.is_err());
+/// assert_eq!(vec![2, 1], *rolled_back.borrow());
+///
+/// rolled_back.borrow_mut().clear();
+///
+/// let result = try_process_all(
+///     ["1", "2", "3"],
+///     |s| s.parse::<u8>(),
+///     |n| rolled_back.borrow_mut().push(*n),
+/// );
+/// assert_eq!(Ok(vec![1, 2, 3]), result);
+/// assert!(rolled_back.borrow().is_empty());
+/// ```
+pub fn try_process_all<I, O, E, P, R>(
+    items: I,
+    mut process: P,
+    rollback_item: R,
+) -> Result<Vec<O>, E>
+where
+    I: IntoIterator,
+    P: FnMut(I::Item) -> Result<O, E>,
+    R: Fn(&O),
+{
+    let outputs = RefCell::new(Vec::new());
+    // Declared after `outputs`, so that it is dropped (and thus rolled back) first.
+    let mut stack = RollbackStack::new(ExecutionOrder::Lifo);
+
+    for item in items {
+        let output = process(item)?;
+        let index = {
+            let mut outputs = outputs.borrow_mut();
+            outputs.push(output);
+            outputs.len() - 1
+        };
+        let (outputs, rollback_item) = (&outputs, &rollback_item);
+        stack.push(infallible_rollback(move || {
+            rollback_item(&outputs.borrow()[index])
+        }));
+    }
+
+    stack.ok();
+    Ok(outputs.into_inner())
+}
@@ -0,0 +1,105 @@
+use crate::{StateOf, Transaction, TransactionState};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// A budget of rollbacks shared by a group of transactions, acting as a circuit breaker.
+///
+/// Each transaction executed via [`RollbackBudget::execute`] whose rollback ran uses up one
+/// rollback of the budget, regardless of whether the rollback succeeded. Once the budget is
+/// exhausted, transactions executed via the budget are not run anymore: Nothing of them runs,
+/// not even [`Transaction::precondition`], and their state is [`TransactionState::Skipped`]
+/// with [`BudgetExhausted`] converted into their `BeforeError`. This protects a system against a
+/// flood of operations that fail and roll back.
+///
+/// The budget is a handle to a shared atomic counter: Clones share the same budget and can be
+/// sent to other threads. The budget is checked before a transaction runs, so transactions
+/// that run concurrently may together roll back more often than the budget allows. Their
+/// rollbacks are still counted, and all transactions started afterwards are skipped.
+///
+/// ```rust
+/// use transaction_rollback::{BudgetExhausted, Closures, RollbackBudget};
+///
+/// let budget = RollbackBudget::new(2);
+/// let states: Vec<_> = (0..4)
+///     .map(|_| {
+///         budget.execute(Closures {
+///             before: || Ok::<_, BudgetExhausted>(()),
+///             operation: || Err::<(), _>("failed"),
+///             rollback: |_: &_| Ok::<_, ()>(()),
+///             finally: || Ok::<_, ()>(()),
+///         })
+///     })
+///     .collect();
+///
+/// assert!(!states[1].is_skipped());
+/// assert_eq!(Some(&BudgetExhausted), states[2].skip_reason());
+/// assert_eq!(Some(&BudgetExhausted), states[3].skip_reason());
+/// assert!(budget.is_exhausted());
+/// ```
+#[derive(Debug, Clone)]
+pub struct RollbackBudget {
+    remaining: Arc<AtomicUsize>,
+}
+
+impl RollbackBudget {
+    /// Creates a budget that allows `rollbacks` rollbacks.
+    pub fn new(rollbacks: usize) -> Self {
+        Self {
+            remaining: Arc::new(AtomicUsize::new(rollbacks)),
+        }
+    }
+
+    /// The number of rollbacks left in the budget.
+    pub fn remaining(&self) -> usize {
+        self.remaining.load(Ordering::Acquire)
+    }
+
+    /// Returns `true` if no rollback is left, in which case no further transaction is run.
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Executes `transaction`, see [`Transaction::execute`], if the budget is not exhausted.
+    ///
+    /// If its rollback runs, one rollback is used up. If the budget is already exhausted,
+    /// `transaction` is not run and [`TransactionState::Skipped`] is returned instead, see
+    /// [`RollbackBudget`].
+    ///
+    /// # Panics
+    /// If executing the transaction panics, see [`Transaction::execute`]. A panicking
+    /// transaction does not use up the budget.
+    pub fn execute<T>(&self, transaction: T) -> StateOf<T>
+    where
+        T: Transaction,
+        T::BeforeError: From<BudgetExhausted>,
+    {
+        if self.is_exhausted() {
+            return TransactionState::Skipped(BudgetExhausted.into());
+        }
+        let state = transaction.execute();
+        if matches!(
+            state,
+            TransactionState::Rollback(..) | TransactionState::RollbackButFailedFinally(..)
+        ) {
+            // Saturates at zero, since concurrent transactions may exceed the budget.
+            let _ = self
+                .remaining
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1));
+        }
+        state
+    }
+}
+
+/// The reason a transaction was skipped by an exhausted [`RollbackBudget`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BudgetExhausted;
+
+impl Display for BudgetExhausted {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The rollback budget is exhausted")
+    }
+}
+
+impl Error for BudgetExhausted {}
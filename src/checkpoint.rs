@@ -0,0 +1,122 @@
+use crate::rollback::private;
+use crate::{rollback, MaybeError, RollbackGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A rollback that is dismissed once a later checkpoint is reached.
+///
+/// Returns the guard together with its [`Checkpoint`]. Calling [`Checkpoint::reached`]
+/// dismisses the guard, otherwise it rolls back as usual when dropped. This models "undo stage
+/// 1 unless we got past stage 2", where the checkpoint may be reached in a distant code path
+/// that does not own the guard.
+///
+/// The guard and the checkpoint are independent values that only share a flag: The
+/// checkpoint can be moved anywhere, including to other threads, and may outlive the guard.
+/// The guard checks the flag when it is rolled back. If the checkpoint was reached by then,
+/// the action is not run and the result is `Ok(None)`, otherwise the result of the action is
+/// wrapped in `Some`. Reaching the checkpoint after the guard was rolled back has no effect,
+/// and dropping the checkpoint without reaching it keeps the guard armed.
+///
+/// ```rust
+/// # use std::cell::Cell;
+/// use transaction_rollback::rollback_until_checkpoint;
+///
+/// let rolled_back = Cell::new(0);
+/// let stage = |succeed: bool| {
+///     let (_guard, checkpoint) =
+///         rollback_until_checkpoint(|| Ok::<_, ()>(rolled_back.set(rolled_back.get() + 1)));
+///     // ... stage 2, which may be far away ...
+///     if succeed {
+///         checkpoint.reached();
+///     }
+/// };
+///
+/// stage(true);
+/// assert_eq!(0, rolled_back.get());
+/// stage(false);
+/// assert_eq!(1, rolled_back.get());
+/// ```
+pub fn rollback_until_checkpoint<'a, F, T, E>(
+    rollback_action: F,
+) -> (RollbackGuard<'a, Option<T>, E>, Checkpoint)
+where
+    F: FnOnce() -> Result<T, E> + 'a,
+    E: MaybeError,
+    RollbackGuard<'a, Option<T>, E>: private::DropLike,
+{
+    let reached = Arc::new(AtomicBool::new(false));
+    let checkpoint = Checkpoint {
+        reached: reached.clone(),
+    };
+    let guard = rollback(move || {
+        if reached.load(Ordering::Acquire) {
+            Ok(None)
+        } else {
+            rollback_action().map(Some)
+        }
+    });
+    (guard, checkpoint)
+}
+
+/// A checkpoint that dismisses its guard when reached.
+///
+/// To create this and for more information see [`rollback_until_checkpoint`].
+#[derive(Debug)]
+pub struct Checkpoint {
+    reached: Arc<AtomicBool>,
+}
+
+impl Checkpoint {
+    /// Marks the checkpoint as reached, which dismisses its guard.
+    pub fn reached(self) {
+        self.reached.store(true, Ordering::Release);
+    }
+}
+
+/// A rollback that is cancelled if `flag` is set when it is rolled back.
+///
+/// The flag is read lazily, each time the guard is rolled back, either on drop or via
+/// [`crate::Rollback::do_rollback`]. If it is set, the action is not run and the result is
+/// `Ok(None)`, otherwise the result of the action is wrapped in `Some`. Setting the flag thus
+/// effectively commits, which is useful if another part of the system decides asynchronously
+/// whether the work succeeded. Unlike [`RollbackGuard::disarm`], the flag is not owned by the
+/// guard and can be set by anyone who has access to it.
+///
+/// The flag is read with [`Ordering::Acquire`]. If it is set with [`Ordering::Release`] or
+/// stronger, everything the setting thread did before is visible after the guard observed the
+/// flag.
+///
+/// ```rust
+/// # use std::cell::Cell;
+/// use std::sync::atomic::{AtomicBool, Ordering};
+/// use transaction_rollback::cancel_on;
+///
+/// let committed = AtomicBool::new(false);
+/// let rolled_back = Cell::new(false);
+///
+/// drop(cancel_on(&committed, || Ok::<_, ()>(rolled_back.set(true))));
+/// assert!(rolled_back.get());
+///
+/// rolled_back.set(false);
+/// let guard = cancel_on(&committed, || Ok::<_, ()>(rolled_back.set(true)));
+/// committed.store(true, Ordering::Release);
+/// drop(guard);
+/// assert!(!rolled_back.get());
+/// ```
+pub fn cancel_on<'a, F, T, E>(
+    flag: &'a AtomicBool,
+    rollback_action: F,
+) -> RollbackGuard<'a, Option<T>, E>
+where
+    F: FnOnce() -> Result<T, E> + 'a,
+    E: MaybeError,
+    RollbackGuard<'a, Option<T>, E>: private::DropLike,
+{
+    rollback(move || {
+        if flag.load(Ordering::Acquire) {
+            Ok(None)
+        } else {
+            rollback_action().map(Some)
+        }
+    })
+}
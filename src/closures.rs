@@ -0,0 +1,180 @@
+use crate::{Transaction, TransactionState};
+use std::marker::PhantomData;
+
+/// Creates a [`Transaction`] from a single fallible operation and a rollback for it.
+///
+/// The returned transaction has no-op [`Transaction::before`] and [`Transaction::finally`]
+/// phases, which can not fail (their error types are `()`). The types of the transaction are
+/// inferred from the closures.
+///
+/// ```rust
+/// # use std::cell::Cell;
+/// use transaction_rollback::{simple_transaction, Transaction, TransactionState};
+///
+/// let rolled_back = Cell::new(false);
+/// let state = simple_transaction(
+///     || Err::<(), _>("nope"),
+///     |_err| -> Result<(), ()> {
+///         rolled_back.set(true);
+///         Ok(())
+///     },
+/// )
+/// .execute();
+///
+/// assert_eq!(TransactionState::Rollback("nope", Ok(())), state);
+/// assert!(rolled_back.get());
+/// ```
+pub fn simple_transaction<F, R, O, E, RE>(
+    operation: F,
+    rollback: R,
+) -> SimpleTransaction<F, R, O, E, RE>
+where
+    F: FnOnce() -> Result<O, E>,
+    R: FnOnce(&E) -> Result<(), RE>,
+{
+    SimpleTransaction {
+        operation: Some(operation),
+        rollback: Some(rollback),
+        _types: PhantomData,
+    }
+}
+
+/// A [`Transaction`] made from an operation and a rollback closure.
+///
+/// To create this see [`simple_transaction`].
+pub struct SimpleTransaction<F, R, O, E, RE> {
+    operation: Option<F>,
+    rollback: Option<R>,
+    #[allow(clippy::type_complexity)]
+    _types: PhantomData<fn() -> (O, E, RE)>,
+}
+
+impl<F, R, O, E, RE> Transaction for SimpleTransaction<F, R, O, E, RE>
+where
+    F: FnOnce() -> Result<O, E>,
+    R: FnOnce(&E) -> Result<(), RE>,
+{
+    type BeforeError = ();
+    type Ok = O;
+    type Error = E;
+    type RollbackOk = ();
+    type RollbackError = RE;
+    type FinallyError = ();
+
+    fn before(&mut self) -> Result<(), Self::BeforeError> {
+        Ok(())
+    }
+
+    fn operation(&mut self) -> Result<Self::Ok, Self::Error> {
+        let operation = self
+            .operation
+            .take()
+            .expect("the operation of a SimpleTransaction must only run once");
+        operation()
+    }
+
+    fn rollback(
+        &mut self,
+        err_operation: &Self::Error,
+    ) -> Result<Self::RollbackOk, Self::RollbackError> {
+        let rollback = self
+            .rollback
+            .take()
+            .expect("the rollback of a SimpleTransaction must only run once");
+        rollback(err_operation)
+    }
+
+    fn finally(
+        &mut self,
+        _state: &TransactionState<
+            Self::BeforeError,
+            Self::Ok,
+            Self::Error,
+            Self::RollbackOk,
+            Self::RollbackError,
+            Self::FinallyError,
+        >,
+    ) -> Result<(), Self::FinallyError> {
+        Ok(())
+    }
+}
+
+/// A [`Transaction`] made from one closure per phase.
+///
+/// This allows constructing a transaction as a plain struct literal, without implementing the
+/// trait. The associated types of the transaction are inferred from the signatures of the
+/// closures, which are listed at the fields.
+///
+/// Note that `finally` does not get access to the state of the transaction, since the error
+/// type could then not be inferred from the closure. If it is needed, implement [`Transaction`]
+/// instead.
+///
+/// ```rust
+/// use transaction_rollback::{Closures, Transaction, TransactionState};
+///
+/// let transaction = Closures {
+///     before: || Ok::<_, ()>(()),
+///     operation: || Err::<(), _>("failed"),
+///     rollback: |_err: &&str| Ok::<_, ()>("rolled back"),
+///     finally: || Ok::<_, ()>(()),
+/// };
+///
+/// assert_eq!(
+///     TransactionState::Rollback("failed", Ok("rolled back")),
+///     transaction.execute(),
+/// );
+/// ```
+pub struct Closures<B, Op, R, F> {
+    /// [`Transaction::before`], an `FnMut() -> Result<(), BeforeError>`.
+    pub before: B,
+    /// [`Transaction::operation`], an `FnMut() -> Result<Ok, Error>`.
+    pub operation: Op,
+    /// [`Transaction::rollback`], an `FnMut(&Error) -> Result<RollbackOk, RollbackError>`.
+    pub rollback: R,
+    /// [`Transaction::finally`], an `FnMut() -> Result<(), FinallyError>`, without the state.
+    pub finally: F,
+}
+
+impl<B, Op, R, F, BE, O, E, RO, RE, FE> Transaction for Closures<B, Op, R, F>
+where
+    B: FnMut() -> Result<(), BE>,
+    Op: FnMut() -> Result<O, E>,
+    R: FnMut(&E) -> Result<RO, RE>,
+    F: FnMut() -> Result<(), FE>,
+{
+    type BeforeError = BE;
+    type Ok = O;
+    type Error = E;
+    type RollbackOk = RO;
+    type RollbackError = RE;
+    type FinallyError = FE;
+
+    fn before(&mut self) -> Result<(), Self::BeforeError> {
+        (self.before)()
+    }
+
+    fn operation(&mut self) -> Result<Self::Ok, Self::Error> {
+        (self.operation)()
+    }
+
+    fn rollback(
+        &mut self,
+        err_operation: &Self::Error,
+    ) -> Result<Self::RollbackOk, Self::RollbackError> {
+        (self.rollback)(err_operation)
+    }
+
+    fn finally(
+        &mut self,
+        _state: &TransactionState<
+            Self::BeforeError,
+            Self::Ok,
+            Self::Error,
+            Self::RollbackOk,
+            Self::RollbackError,
+            Self::FinallyError,
+        >,
+    ) -> Result<(), Self::FinallyError> {
+        (self.finally)()
+    }
+}
@@ -0,0 +1,70 @@
+/// A guard that runs `action` when dropped, but only if it was explicitly armed.
+///
+/// This is the opposite of a [`crate::RollbackGuard`]: a rollback guard runs unless it is
+/// dismissed via [`crate::RollbackGuard::ok`], while a commit guard does nothing unless
+/// [`CommitGuard::arm`] is called. Calling `arm` at the point where the scope succeeded means
+/// the action only runs if that point was reached, for example if no `?` returned early before.
+///
+/// ```rust
+/// # use std::cell::Cell;
+/// use transaction_rollback::commit_guard;
+///
+/// fn work(committed: &Cell<bool>, fail: bool) -> Result<(), ()> {
+///     let mut guard = commit_guard(|| committed.set(true));
+///     if fail {
+///         Err(())?;
+///     }
+///     guard.arm();
+///     Ok(())
+/// }
+///
+/// let committed = Cell::new(false);
+/// work(&committed, true).ok();
+/// assert!(!committed.get());
+/// work(&committed, false).ok();
+/// assert!(committed.get());
+/// ```
+pub fn commit_guard<'a, F>(action: F) -> CommitGuard<'a>
+where
+    F: FnOnce() + 'a,
+{
+    CommitGuard {
+        action: Some(Box::new(action)),
+        armed: false,
+    }
+}
+
+/// A guard that runs an action on drop, if it was armed.
+///
+/// To create this and for more information see [`commit_guard`].
+pub struct CommitGuard<'a> {
+    action: Option<Box<dyn FnOnce() + 'a>>,
+    armed: bool,
+}
+
+impl<'a> CommitGuard<'a> {
+    /// Arms the guard, so that its action runs when it is dropped.
+    pub fn arm(&mut self) {
+        self.armed = true;
+    }
+
+    /// Disarms the guard again, so that its action does not run when it is dropped.
+    pub fn disarm(&mut self) {
+        self.armed = false;
+    }
+
+    /// Whether the action will run when the guard is dropped.
+    pub fn is_armed(&self) -> bool {
+        self.armed
+    }
+}
+
+impl<'a> Drop for CommitGuard<'a> {
+    fn drop(&mut self) {
+        if self.armed {
+            if let Some(action) = self.action.take() {
+                action()
+            }
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The default maximum for how deeply transactions may be nested on one thread.
+pub const DEFAULT_MAX_TRANSACTION_DEPTH: usize = 64;
+
+static MAX_TRANSACTION_DEPTH: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_TRANSACTION_DEPTH);
+
+thread_local! {
+    static TRANSACTION_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Sets how deeply transactions may be nested on one thread, for all threads.
+///
+/// A transaction is nested if it is executed while another transaction is executing on the same
+/// thread, for example inside its `operation` or `rollback`. If executing a transaction would
+/// exceed the maximum depth, [`crate::Transaction::execute`] (and the other `execute` methods)
+/// panic instead. This guards against runaway recursion, where rollbacks trigger further
+/// transactions indefinitely.
+///
+/// The default is [`DEFAULT_MAX_TRANSACTION_DEPTH`].
+///
+/// ```should_panic
+/// use transaction_rollback::{
+///     set_max_transaction_depth, simple_transaction, Transaction, TransactionState,
+/// };
+///
+/// fn recurse() -> TransactionState<(), (), (), (), (), ()> {
+///     // Every rollback executes the transaction again.
+///     simple_transaction(|| Err(()), |_| {
+///         recurse();
+///         Ok(())
+///     })
+///     .execute()
+/// }
+///
+/// set_max_transaction_depth(8);
+/// // Panics once the ninth nested transaction is executed.
+/// recurse();
+/// ```
+pub fn set_max_transaction_depth(depth: usize) {
+    MAX_TRANSACTION_DEPTH.store(depth, Ordering::Relaxed);
+}
+
+/// Returns how deeply transactions may be nested on one thread, see
+/// [`set_max_transaction_depth`].
+pub fn max_transaction_depth() -> usize {
+    MAX_TRANSACTION_DEPTH.load(Ordering::Relaxed)
+}
+
+/// Returns how many transactions are currently executing on this thread.
+///
+/// This is `0` outside of transactions, `1` inside the phases of a transaction and increases
+/// by one for each nested transaction. The count is per thread, so transactions executing on
+/// other threads are not included, and work moved to other threads, for example by an async
+/// executor, does not see the count of the thread it was started from.
+///
+/// ```rust
+/// # use std::cell::Cell;
+/// use transaction_rollback::{current_transaction_depth, simple_transaction, Transaction};
+///
+/// let depths = Cell::new((0, 0));
+/// assert_eq!(0, current_transaction_depth());
+///
+/// simple_transaction::<_, _, _, (), ()>(
+///     || {
+///         let outer = current_transaction_depth();
+///         simple_transaction::<_, _, _, (), ()>(
+///             || {
+///                 depths.set((outer, current_transaction_depth()));
+///                 Ok(())
+///             },
+///             |_| Ok(()),
+///         )
+///         .execute();
+///         Ok(())
+///     },
+///     |_| Ok(()),
+/// )
+/// .execute();
+///
+/// assert_eq!((1, 2), depths.get());
+/// assert_eq!(0, current_transaction_depth());
+/// ```
+pub fn current_transaction_depth() -> usize {
+    TRANSACTION_DEPTH.with(Cell::get)
+}
+
+/// Tracks that a transaction is executing on the current thread, for as long as it lives.
+pub(crate) struct DepthGuard(());
+
+impl DepthGuard {
+    /// Enters a transaction.
+    ///
+    /// # Panics
+    /// If this exceeds the maximum transaction depth.
+    pub(crate) fn enter() -> Self {
+        let depth = TRANSACTION_DEPTH.with(|d| {
+            d.set(d.get() + 1);
+            d.get()
+        });
+        // Created before checking, so that the depth is restored if we panic.
+        let guard = DepthGuard(());
+        let max = max_transaction_depth();
+        if depth > max {
+            panic!(
+                "transaction-rollback: maximum transaction depth of {max} exceeded; \
+                 transactions are probably nested recursively. \
+                 See `transaction_rollback::set_max_transaction_depth`."
+            );
+        }
+        guard
+    }
+}
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        TRANSACTION_DEPTH.with(|d| d.set(d.get() - 1));
+    }
+}
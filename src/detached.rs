@@ -0,0 +1,195 @@
+use crate::rollback::{handle_drop_failure, private};
+use crate::{MaybeError, Rollback, RollbackGuard};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+/// The number of rollbacks currently running on a background thread, see
+/// [`join_detached_rollbacks`].
+static OUTSTANDING: (Mutex<usize>, Condvar) = (Mutex::new(0), Condvar::new());
+
+/// A rollback that runs on a background thread when dropped.
+///
+/// This works like [`rollback()`], but dropping the guard spawns a new thread to run the
+/// action on, instead of blocking the dropping scope until the action finished. This is
+/// useful for slow cleanup whose completion does not need to be awaited. Rolling back via
+/// [`Rollback::do_rollback`] still runs the action synchronously and returns its result.
+///
+/// Note that dropping the guard has the cost of spawning a thread, and that the action may
+/// still run after the scope that dropped the guard, or even `main`, has ended. Use
+/// [`join_detached_rollbacks`] to wait for all actions running in the background. If no thread
+/// can be spawned, the action runs on the dropping thread instead.
+///
+/// Failures of an action running in the background are passed to the handler registered via
+/// [`DetachedRollbackGuard::on_failure`]. Without a handler, they are handled like failures of
+/// a dropped [`RollbackGuard`] on the background thread: only a globally installed
+/// [`try_drop::TryDropStrategy`] applies, thread-local ones of the dropping thread do not.
+///
+/// ```rust
+/// use std::sync::atomic::{AtomicBool, Ordering};
+/// use std::sync::Arc;
+/// use std::thread;
+/// use std::time::Duration;
+/// use transaction_rollback::{detached_rollback, join_detached_rollbacks};
+///
+/// let closed = Arc::new(AtomicBool::new(false));
+/// let guard = detached_rollback::<_, _, ()>({
+///     let closed = closed.clone();
+///     move || {
+///         thread::sleep(Duration::from_millis(50));
+///         closed.store(true, Ordering::SeqCst);
+///         Ok(())
+///     }
+/// });
+///
+/// // Does not wait for the action.
+/// drop(guard);
+/// join_detached_rollbacks();
+/// assert!(closed.load(Ordering::SeqCst));
+/// ```
+pub fn detached_rollback<F, T, E>(rollback_action: F) -> DetachedRollbackGuard<T, E>
+where
+    F: FnOnce() -> Result<T, E> + Send + 'static,
+    T: 'static,
+    E: MaybeError + 'static,
+    RollbackGuard<'static, T, E>: private::DropLike,
+{
+    DetachedRollbackGuard {
+        rollback_action: Some(Box::new(rollback_action)),
+        on_failure: None,
+    }
+}
+
+/// Waits until all rollbacks of [`detached_rollback`] guards that run on a background thread
+/// have finished, including rollbacks started while waiting.
+///
+/// A rollback that panics counts as finished.
+pub fn join_detached_rollbacks() {
+    let (count, finished) = &OUTSTANDING;
+    let mut count = count.lock().unwrap_or_else(|e| e.into_inner());
+    while *count > 0 {
+        count = finished.wait(count).unwrap_or_else(|e| e.into_inner());
+    }
+}
+
+/// A rollback that runs on a background thread when dropped.
+///
+/// To create this and for more information see [`detached_rollback`].
+pub struct DetachedRollbackGuard<T, E>
+where
+    T: 'static,
+    E: MaybeError + 'static,
+    RollbackGuard<'static, T, E>: private::DropLike,
+{
+    rollback_action: Option<Box<dyn FnOnce() -> Result<T, E> + Send>>,
+    on_failure: Option<Box<dyn FnOnce(E) + Send>>,
+}
+
+impl<T, E> DetachedRollbackGuard<T, E>
+where
+    T: 'static,
+    E: MaybeError + 'static,
+    RollbackGuard<'static, T, E>: private::DropLike,
+{
+    /// Registers a handler for a failure of the action, if it runs in the background because
+    /// the guard was dropped.
+    ///
+    /// The handler runs on the background thread, or on the dropping thread if no thread could
+    /// be spawned. It is not invoked by
+    /// [`Rollback::do_rollback`], since that returns the failure directly.
+    pub fn on_failure<F>(mut self, on_failure: F) -> Self
+    where
+        F: FnOnce(E) + Send + 'static,
+    {
+        self.on_failure = Some(Box::new(on_failure));
+        self
+    }
+
+    /// Drops the rollback guard but does not run the rollback function.
+    pub fn ok(mut self) {
+        self.rollback_action.take();
+    }
+}
+
+impl<T, E> Rollback for DetachedRollbackGuard<T, E>
+where
+    T: 'static,
+    E: MaybeError + 'static,
+    RollbackGuard<'static, T, E>: private::DropLike,
+{
+    type RollbackOk = T;
+    type RollbackError = E;
+
+    /// Performs the rollback synchronously on the current thread, consuming the guard.
+    fn do_rollback(mut self) -> Result<T, E> {
+        match self.rollback_action.take() {
+            Some(action) => action(),
+            None => unreachable!("the action is only taken when the guard is consumed"),
+        }
+    }
+}
+
+impl<T, E> Drop for DetachedRollbackGuard<T, E>
+where
+    T: 'static,
+    E: MaybeError + 'static,
+    RollbackGuard<'static, T, E>: private::DropLike,
+{
+    fn drop(&mut self) {
+        let Some(action) = self.rollback_action.take() else {
+            return;
+        };
+        let on_failure = self.on_failure.take();
+        // Created before spawning, so that `join_detached_rollbacks` waits for the action.
+        let outstanding = Outstanding::new();
+        let run = move || {
+            let _outstanding = outstanding;
+            match on_failure {
+                Some(on_failure) => {
+                    if let Err(e) = action() {
+                        on_failure(e)
+                    }
+                }
+                // Handled like the failure of a dropped guard.
+                None => {
+                    if let Err(err) = action() {
+                        handle_drop_failure::<T, _>(err);
+                    }
+                }
+            }
+        };
+        // Shared with the thread, since a failed spawn drops the closure passed to it.
+        let run = Arc::new(Mutex::new(Some(run)));
+        let spawned = thread::Builder::new().spawn({
+            let run = run.clone();
+            move || {
+                if let Some(run) = run.lock().unwrap_or_else(|e| e.into_inner()).take() {
+                    run()
+                }
+            }
+        });
+        if spawned.is_err() {
+            // No thread could be spawned, so the action runs on the dropping thread instead.
+            if let Some(run) = run.lock().unwrap_or_else(|e| e.into_inner()).take() {
+                run()
+            }
+        }
+    }
+}
+
+/// Marks a background rollback as running until dropped, also if it panicked.
+struct Outstanding;
+
+impl Outstanding {
+    fn new() -> Self {
+        *OUTSTANDING.0.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+        Outstanding
+    }
+}
+
+impl Drop for Outstanding {
+    fn drop(&mut self) {
+        let (count, finished) = &OUTSTANDING;
+        *count.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        finished.notify_all();
+    }
+}
@@ -0,0 +1,190 @@
+use crate::depth::DepthGuard;
+use crate::transaction::with_finally_error;
+use crate::{StateOf, Transaction, TransactionPhase, TransactionState};
+
+/// Executes a transaction one phase at a time, controlled by the caller.
+///
+/// Each call to [`TransactionDriver::step`] runs the next phase of the transaction and returns
+/// [`StepResult::Ran`] with it. Between steps, the caller can inspect the transaction and the
+/// upcoming phase, which is useful in tests and for debugging. The phases run in the same
+/// order and under the same conditions as in [`Transaction::execute`]:
+///
+/// 1. [`Transaction::before`]. If it fails, no further phase runs.
+/// 2. [`Transaction::operation`], then [`Transaction::validate`] if it succeeded.
+/// 3. [`Transaction::rollback`], if one of them failed and [`Transaction::should_rollback`]
+///    returns `true` for the error.
+/// 4. [`Transaction::finally`], unless `before` failed or [`Transaction::skip_finally`]
+///    returns `true`.
+///
+/// Once no phase is left, the next step returns [`StepResult::Finished`] with the final state,
+/// which is the same state [`Transaction::execute`] would have returned. The driver can not be
+/// stepped any further afterwards. Panics are not caught.
+///
+/// ```rust
+/// use transaction_rollback::{
+///     simple_transaction, StepResult, TransactionDriver, TransactionPhase, TransactionState,
+/// };
+///
+/// let transaction = simple_transaction(|| Err::<(), _>("oh no"), |_| Ok::<_, ()>(()));
+/// let mut driver = TransactionDriver::new(transaction);
+///
+/// assert_eq!(StepResult::Ran(TransactionPhase::Before), driver.step());
+/// assert_eq!(StepResult::Ran(TransactionPhase::Operation), driver.step());
+/// // The operation failed, so it is rolled back next.
+/// assert_eq!(Some(TransactionPhase::Rollback), driver.next_phase());
+/// assert_eq!(StepResult::Ran(TransactionPhase::Rollback), driver.step());
+/// assert_eq!(StepResult::Ran(TransactionPhase::Finally), driver.step());
+/// assert_eq!(None, driver.next_phase());
+/// assert_eq!(
+///     StepResult::Finished(TransactionState::Rollback("oh no", Ok(()))),
+///     driver.step()
+/// );
+/// ```
+pub struct TransactionDriver<T>
+where
+    T: Transaction,
+{
+    transaction: T,
+    // `None` once finished.
+    next: Option<Next<T>>,
+}
+
+/// What a [`TransactionDriver`] does on its next step.
+enum Next<T>
+where
+    T: Transaction,
+{
+    Before,
+    Operation,
+    Validate(T::Ok),
+    Rollback(T::Error),
+    Finally(StateOf<T>),
+    Finish(StateOf<T>),
+}
+
+/// The result of [`TransactionDriver::step`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepResult<S> {
+    /// The phase ran and the transaction is not finished yet.
+    Ran(TransactionPhase),
+    /// No phase was left to run, the transaction finished with this state.
+    Finished(S),
+}
+
+impl<T> TransactionDriver<T>
+where
+    T: Transaction,
+{
+    /// Wraps `transaction`, to be executed step by step. No phase runs until the first step.
+    pub fn new(transaction: T) -> Self {
+        Self {
+            transaction,
+            next: Some(Next::Before),
+        }
+    }
+
+    /// Runs the next phase, or returns the final state if no phase is left.
+    ///
+    /// # Panics
+    /// If the driver already returned [`StepResult::Finished`], or if the phase would exceed
+    /// the maximum depth of nested transactions, see [`crate::set_max_transaction_depth`].
+    pub fn step(&mut self) -> StepResult<StateOf<T>> {
+        let next = self.next.take().unwrap_or_else(|| {
+            panic!("transaction-rollback: the transaction driver was stepped after it finished.")
+        });
+        let _depth = DepthGuard::enter();
+        let (phase, next) = match next {
+            Next::Before => (
+                TransactionPhase::Before,
+                match self.transaction.before() {
+                    Ok(()) => Next::Operation,
+                    Err(e) => Next::Finish(TransactionState::FailedBefore(e)),
+                },
+            ),
+            Next::Operation => (
+                TransactionPhase::Operation,
+                match self.transaction.operation() {
+                    Ok(o) => Next::Validate(o),
+                    Err(e) => self.failed(e),
+                },
+            ),
+            Next::Validate(o) => (
+                TransactionPhase::Validate,
+                match self.transaction.validate(&o) {
+                    Ok(()) => self.finally(TransactionState::Ok(o)),
+                    Err(e) => self.failed(e),
+                },
+            ),
+            Next::Rollback(e) => {
+                let rollback_result = self.transaction.rollback(&e);
+                (
+                    TransactionPhase::Rollback,
+                    self.finally(TransactionState::Rollback(e, rollback_result)),
+                )
+            }
+            Next::Finally(state) => (
+                TransactionPhase::Finally,
+                Next::Finish(match self.transaction.finally(&state) {
+                    Ok(()) => state,
+                    Err(e) => with_finally_error(state, e),
+                }),
+            ),
+            Next::Finish(state) => return StepResult::Finished(state),
+        };
+        self.next = Some(next);
+        StepResult::Ran(phase)
+    }
+
+    /// Runs all remaining phases and returns the final state.
+    ///
+    /// # Panics
+    /// See [`Self::step`].
+    pub fn run(mut self) -> StateOf<T> {
+        loop {
+            if let StepResult::Finished(state) = self.step() {
+                return state;
+            }
+        }
+    }
+
+    /// The phase the next step runs, or `None` if it returns the final state or the driver
+    /// already finished.
+    pub fn next_phase(&self) -> Option<TransactionPhase> {
+        match self.next.as_ref()? {
+            Next::Before => Some(TransactionPhase::Before),
+            Next::Operation => Some(TransactionPhase::Operation),
+            Next::Validate(_) => Some(TransactionPhase::Validate),
+            Next::Rollback(_) => Some(TransactionPhase::Rollback),
+            Next::Finally(_) => Some(TransactionPhase::Finally),
+            Next::Finish(_) => None,
+        }
+    }
+
+    /// Returns `true` if the driver already returned [`StepResult::Finished`].
+    pub fn is_finished(&self) -> bool {
+        self.next.is_none()
+    }
+
+    /// The driven transaction, for inspection between steps.
+    pub fn transaction(&self) -> &T {
+        &self.transaction
+    }
+
+    /// The step after the operation or validation failed with `err`.
+    fn failed(&self, err: T::Error) -> Next<T> {
+        if self.transaction.should_rollback(&err) {
+            Next::Rollback(err)
+        } else {
+            self.finally(TransactionState::FailedNoRollback(err))
+        }
+    }
+
+    /// The step after the operation and possibly the rollback ran.
+    fn finally(&self, state: StateOf<T>) -> Next<T> {
+        if self.transaction.skip_finally(&state) {
+            Next::Finish(state)
+        } else {
+            Next::Finally(state)
+        }
+    }
+}
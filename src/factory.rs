@@ -0,0 +1,59 @@
+use crate::rollback::private;
+use crate::{infallible_rollback, rollback, MaybeError, RollbackGuard};
+
+/// Creates rollback guards for individual items, that all share the same cleanup logic.
+///
+/// The guards borrow the factory, so it must outlive them.
+///
+/// ```rust
+/// # use std::cell::RefCell;
+/// use transaction_rollback::RollbackFactory;
+///
+/// let deleted = RefCell::new(Vec::new());
+/// let delete_file = RollbackFactory::new(|path: &str| deleted.borrow_mut().push(path.to_string()));
+///
+/// let guards: Vec<_> = ["a.txt", "b.txt"]
+///     .into_iter()
+///     .map(|path| delete_file.infallible_guard(path))
+///     .collect();
+/// drop(guards);
+///
+/// assert_eq!(vec!["a.txt", "b.txt"], *deleted.borrow());
+/// ```
+pub struct RollbackFactory<F> {
+    cleanup: F,
+}
+
+impl<F> RollbackFactory<F> {
+    /// Creates a new factory with the given cleanup logic.
+    ///
+    /// For guards created via [`Self::guard`], `cleanup` must implement
+    /// `Fn(Item) -> Result<T, E>`, like the action of [`rollback()`]. For guards created via
+    /// [`Self::infallible_guard`], it must implement `Fn(Item) -> T`, like the action of
+    /// [`infallible_rollback`].
+    pub fn new(cleanup: F) -> Self {
+        Self { cleanup }
+    }
+
+    /// Creates a guard that runs the cleanup logic for `item`, see [`rollback()`].
+    pub fn guard<'a, I, T, E>(&'a self, item: I) -> RollbackGuard<'a, T, E>
+    where
+        F: Fn(I) -> Result<T, E>,
+        I: 'a,
+        E: MaybeError,
+        RollbackGuard<'a, T, E>: private::DropLike,
+    {
+        rollback(move || (self.cleanup)(item))
+    }
+
+    /// Creates a guard that runs the infallible cleanup logic for `item`, see
+    /// [`infallible_rollback`].
+    pub fn infallible_guard<'a, I, T>(&'a self, item: I) -> RollbackGuard<'a, T, ()>
+    where
+        F: Fn(I) -> T,
+        I: 'a,
+        RollbackGuard<'a, T, ()>: private::DropLike,
+    {
+        infallible_rollback(move || (self.cleanup)(item))
+    }
+}
@@ -0,0 +1,71 @@
+use crate::macros::forward_transaction;
+use crate::{StateOf, Transaction};
+
+/// A transaction whose [`Transaction::finally`] errors are passed to a handler instead of
+/// changing its state.
+///
+/// In many systems a failing `finally` is only worth a warning. Executing this transaction
+/// passes such an error to the handler, for example to log it, and then continues as if
+/// `finally` succeeded. The state is thus never one of the `...ButFailedFinally` variants,
+/// but the plain state before `finally`, like [`crate::TransactionState::Ok`] or
+/// [`crate::TransactionState::Rollback`]. The error is consumed by the handler and not
+/// returned in any way.
+///
+/// `IgnoreFinallyErrors` implements [`Transaction`] itself, so it can be executed in any way.
+///
+/// ```rust
+/// # use std::cell::RefCell;
+/// use transaction_rollback::{Closures, IgnoreFinallyErrors, Transaction, TransactionState};
+///
+/// let warnings = RefCell::new(Vec::new());
+/// let transaction = Closures {
+///     before: || Ok::<_, ()>(()),
+///     operation: || Ok::<_, ()>(42),
+///     rollback: |_: &()| Ok::<_, ()>(()),
+///     finally: || Err("can not remove temporary directory"),
+/// };
+/// let state = IgnoreFinallyErrors::new(transaction, |err| warnings.borrow_mut().push(err))
+///     .execute();
+///
+/// assert_eq!(TransactionState::Ok(42), state);
+/// assert_eq!(vec!["can not remove temporary directory"], *warnings.borrow());
+/// ```
+#[derive(Debug, Clone)]
+pub struct IgnoreFinallyErrors<T, H> {
+    transaction: T,
+    handler: H,
+}
+
+impl<T, H> IgnoreFinallyErrors<T, H>
+where
+    T: Transaction,
+    H: FnMut(T::FinallyError),
+{
+    /// Wraps `transaction`, passing errors of its `finally` to `handler`.
+    pub fn new(transaction: T, handler: H) -> Self {
+        Self {
+            transaction,
+            handler,
+        }
+    }
+
+    /// Returns the inner transaction, without the handler.
+    pub fn into_inner(self) -> T {
+        self.transaction
+    }
+}
+
+impl<T, H> Transaction for IgnoreFinallyErrors<T, H>
+where
+    T: Transaction,
+    H: FnMut(T::FinallyError),
+{
+    forward_transaction! { T, transaction;
+        fn finally(&mut self, state: &StateOf<T>) -> Result<(), Self::FinallyError> {
+            if let Err(err) = self.transaction.finally(state) {
+                (self.handler)(err);
+            }
+            Ok(())
+        }
+    }
+}
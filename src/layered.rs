@@ -0,0 +1,140 @@
+use crate::macros::forward_transaction;
+use crate::{StateOf, Transaction, TransactionState};
+
+/// A transaction with additional `before` and `finally` logic layered around it.
+///
+/// This is middleware for transactions: It allows adding cross-cutting concerns like logging
+/// or metrics uniformly to many transactions, without modifying them. `Layered` implements
+/// [`Transaction`] itself, so it can be executed in any way and layered again.
+///
+/// The phases are nested like this:
+///
+/// 1. The layered `before`. If it fails, its error is the error of [`Transaction::before`] and
+///    nothing else runs.
+/// 2. The [`Transaction::before`] of the inner transaction.
+/// 3. The operation and, if needed, the rollback of the inner transaction.
+/// 4. The [`Transaction::finally`] of the inner transaction, unless its
+///    [`Transaction::skip_finally`] returns `true`.
+/// 5. The layered `finally`. It runs whenever the layered `before` succeeded, also if the
+///    inner `finally` failed or was skipped.
+///
+/// The layered closures use the error types of the inner transaction. If both `finally`s fail,
+/// the error of the inner `finally` is used and the error of the layered `finally` is
+/// discarded. If the inner `before` fails after the layered `before` succeeded, the layered
+/// `finally` runs right away with [`TransactionState::FailedBefore`], so that it can clean up
+/// after the layered `before`. Its error is discarded in this case, since the state of a
+/// transaction whose `before` failed has no room for it.
+///
+/// ```rust
+/// # use std::cell::RefCell;
+/// use transaction_rollback::{
+///     simple_transaction, Closures, Layered, Transaction, TransactionState,
+/// };
+///
+/// let log = RefCell::new(Vec::new());
+/// let transaction = simple_transaction::<_, _, _, (), ()>(
+///     || {
+///         log.borrow_mut().push("operation");
+///         Ok(())
+///     },
+///     |_| Ok(()),
+/// );
+/// let state = Layered::new(
+///     transaction,
+///     || Ok(log.borrow_mut().push("start timer")),
+///     |_| Ok(log.borrow_mut().push("stop timer")),
+/// )
+/// .execute();
+///
+/// assert_eq!(TransactionState::Ok(()), state);
+/// assert_eq!(vec!["start timer", "operation", "stop timer"], *log.borrow());
+///
+/// // The timer is also stopped if the inner `before` fails.
+/// log.borrow_mut().clear();
+/// let transaction = Closures {
+///     before: || Err("not connected"),
+///     operation: || Ok::<_, ()>(()),
+///     rollback: |_: &()| Ok::<_, ()>(()),
+///     finally: || Ok::<_, ()>(()),
+/// };
+/// let state = Layered::new(
+///     transaction,
+///     || Ok(log.borrow_mut().push("start timer")),
+///     |_| Ok(log.borrow_mut().push("stop timer")),
+/// )
+/// .execute();
+///
+/// assert_eq!(TransactionState::FailedBefore("not connected"), state);
+/// assert_eq!(vec!["start timer", "stop timer"], *log.borrow());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Layered<T, B, F> {
+    transaction: T,
+    before: B,
+    finally: F,
+    // Whether the layered `before` succeeded, so the layered `finally` must run.
+    before_succeeded: bool,
+}
+
+impl<T, B, F> Layered<T, B, F>
+where
+    T: Transaction,
+    B: FnMut() -> Result<(), T::BeforeError>,
+    F: FnMut(&StateOf<T>) -> Result<(), T::FinallyError>,
+{
+    /// Layers `before` and `finally` around `transaction`.
+    pub fn new(transaction: T, before: B, finally: F) -> Self {
+        Self {
+            transaction,
+            before,
+            finally,
+            before_succeeded: false,
+        }
+    }
+
+    /// Returns the inner transaction, without the layered closures.
+    pub fn into_inner(self) -> T {
+        self.transaction
+    }
+}
+
+impl<T, B, F> Transaction for Layered<T, B, F>
+where
+    T: Transaction,
+    B: FnMut() -> Result<(), T::BeforeError>,
+    F: FnMut(&StateOf<T>) -> Result<(), T::FinallyError>,
+{
+    forward_transaction! { T, transaction;
+        fn before(&mut self) -> Result<(), Self::BeforeError> {
+            (self.before)()?;
+            self.before_succeeded = true;
+            self.transaction.before().map_err(|err| {
+                // `finally` does not run if `before` failed, so the layered one runs here.
+                let state = TransactionState::FailedBefore(err);
+                let _ = (self.finally)(&state);
+                match state {
+                    TransactionState::FailedBefore(err) => err,
+                    _ => unreachable!(),
+                }
+            })
+        }
+
+        // The layered `finally` must run even if the inner one is skipped, see `finally`.
+        fn skip_finally(&self, _state: &StateOf<T>) -> bool {
+            false
+        }
+
+        fn finally(&mut self, state: &StateOf<T>) -> Result<(), Self::FinallyError> {
+            let result = if self.transaction.skip_finally(state) {
+                Ok(())
+            } else {
+                self.transaction.finally(state)
+            };
+            if self.before_succeeded {
+                result.and((self.finally)(state))
+            } else {
+                result
+            }
+        }
+    }
+}
@@ -38,7 +38,8 @@
 //! To create a more general rollback guard that can potentially fail, use [`rollback()`].
 //! Note that, since [`Drop`] runs the rollback, dropping the guard could fail. Because
 //! of this the returned guard implements [`try_drop::TryDrop`]. You can register handlers
-//! to handle the failure on drop.
+//! to handle the failure on drop. [`drop_strategy_is_configured`] tells you whether this was
+//! done, and [`rollback_checked`] asserts it in debug builds.
 //!
 //! ```should_panic
 //! # use std::borrow::Cow;
@@ -123,6 +124,10 @@
 //! // The rollback code will run.
 //! ```
 //!
+//! Multiple guards can be collected into a [`RollbackStack`], which rolls all of them back
+//! together in a configurable [`ExecutionOrder`]. Alternatively, [`with_rollback_scope`] and
+//! [`scoped_rollback`] collect rollbacks in a thread-local scope, without passing guards around.
+//!
 //! # `Transaction` trait
 //! The [`Transaction`] trait allows for operations to be implemented that can have a wide selection
 //! of set-up and tear-down logic:
@@ -132,8 +137,13 @@
 //! - [`Transaction::before`] is implemented to run code before the actual operation.
 //!   If it fails, the operation is not run, not rolled back and no [`Transaction::finally`]
 //!   is called.
+//! - [`Transaction::validate`] can optionally be implemented to check the result of a successful
+//!   [`Transaction::operation`]. If it fails, the operation is treated as failed.
 //! - [`Transaction::rollback`] is implemented to run the rollback logic, in case
-//!   [`Transaction::operation`] fails. It can also fail.
+//!   [`Transaction::operation`] (or [`Transaction::validate`]) fails.
+//!   It can also fail.
+//!   [`Transaction::should_rollback`] can optionally be implemented to skip the rollback for
+//!   some errors.
 //! - [`Transaction::finally`] is implemented to run after the operation and potential rollback. It
 //!   can also fail.
 //!
@@ -178,11 +188,76 @@
 //! [`UnwindCheckedTransaction`], which provides [`UnwindCheckedTransaction::execute_unwind_checked`],
 //! which is identical to [`Transaction::execute`] except it also catches all panics/unwinds and
 //! converts them into the error types.
+//!
+//! # Features
+//! - `log`: Enables [`Transaction::execute_logged`], which logs the resulting state of a
+//!   transaction via the [`log`](https://docs.rs/log) crate.
+//! - `serde`: Implements [`serde::Serialize`](https://docs.rs/serde) for [`RollbackError`], for
+//!   structured logging of failed rollbacks.
+//! - `tracing`: Enables [`RollbackGuard::traced`], which runs the rollback of a guard inside a
+//!   span of the [`tracing`](https://docs.rs/tracing) crate.
 
+mod acquire;
+mod arena;
+mod batch;
+mod budget;
+mod checkpoint;
+mod closures;
+mod commit;
+mod depth;
+mod detached;
+mod driver;
+mod factory;
+mod ignore_finally;
+mod layered;
+mod macros;
+mod many;
+mod phase;
+mod repeatable;
+mod report;
+mod retry;
 mod rollback;
+mod scope;
+mod slot;
+mod stack;
+mod state;
+#[cfg(feature = "test-util")]
+mod test_util;
+mod timeout;
+mod traced;
 mod transaction;
+mod value;
+mod visitor;
 
 pub use try_drop;
 
+pub use acquire::*;
+pub use arena::*;
+pub use batch::*;
+pub use budget::*;
+pub use checkpoint::*;
+pub use closures::*;
+pub use commit::*;
+pub use depth::*;
+pub use detached::*;
+pub use driver::*;
+pub use factory::*;
+pub use ignore_finally::*;
+pub use layered::*;
+pub use many::*;
+pub use phase::*;
+pub use repeatable::*;
+pub use report::*;
+pub use retry::*;
 pub use rollback::*;
+pub use scope::*;
+pub use slot::*;
+pub use stack::*;
+pub use state::*;
+#[cfg(feature = "test-util")]
+pub use test_util::*;
+pub use timeout::*;
+pub use traced::*;
 pub use transaction::*;
+pub use value::*;
+pub use visitor::*;
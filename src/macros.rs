@@ -0,0 +1,151 @@
+/// Runs a block of fallible steps and rolls back all registered rollbacks if it is left early.
+///
+/// The block consists of statements and `register <closure>;` items. Each `register` adds the
+/// closure as an infallible rollback to a [`RollbackStack`](crate::RollbackStack) that is
+/// local to the block. If the block is left early, for example via `?`, `return` or a
+/// panic, all rollbacks registered so far are run in reverse order. If the end of the block
+/// is reached, they are dismissed.
+///
+/// The block does not evaluate to a value, and variables declared in it are not visible
+/// afterwards.
+///
+/// ```rust
+/// # use std::cell::RefCell;
+/// use transaction_rollback::transaction_scope;
+///
+/// fn setup(log: &RefCell<Vec<&'static str>>, fail: bool) -> Result<(), &'static str> {
+///     transaction_scope! {
+///         log.borrow_mut().push("create dir");
+///         register || log.borrow_mut().push("remove dir");
+///         log.borrow_mut().push("create file");
+///         register || log.borrow_mut().push("remove file");
+///         if fail { Err("can not write file") } else { Ok(()) }?;
+///     }
+///     Ok(())
+/// }
+///
+/// let log = RefCell::new(Vec::new());
+/// assert!(setup(&log, true).is_err());
+/// assert_eq!(
+///     vec!["create dir", "create file", "remove file", "remove dir"],
+///     *log.borrow()
+/// );
+///
+/// log.borrow_mut().clear();
+/// assert!(setup(&log, false).is_ok());
+/// assert_eq!(vec!["create dir", "create file"], *log.borrow());
+/// ```
+#[macro_export]
+macro_rules! transaction_scope {
+    (@munch $stack:ident;) => {};
+    (@munch $stack:ident; register $rollback:expr; $($rest:tt)*) => {
+        $stack.push($crate::infallible_rollback($rollback).ignore_ok());
+        $crate::transaction_scope!(@munch $stack; $($rest)*);
+    };
+    (@munch $stack:ident; $statement:stmt; $($rest:tt)*) => {
+        $statement;
+        $crate::transaction_scope!(@munch $stack; $($rest)*);
+    };
+    ($($body:tt)*) => {{
+        let mut stack = $crate::RollbackStack::<(), ()>::default();
+        $crate::transaction_scope!(@munch stack; $($body)*);
+        stack.ok();
+    }};
+}
+
+/// Implements [`Transaction`](crate::Transaction) for a wrapper by forwarding to the wrapped
+/// transaction of type `$inner` in the field `$field`.
+///
+/// The associated types are the ones of the wrapped transaction. The hooks given as overrides
+/// are used as they are, all other hooks are forwarded. Every hook of the trait must be
+/// forwarded here, so that wrappers do not silently fall back to the default of a hook.
+/// [`Transaction::finally_on_success`](crate::Transaction::finally_on_success) and
+/// [`Transaction::finally_on_rollback`](crate::Transaction::finally_on_rollback) are only
+/// called by the default `finally`, so forwarding `finally` covers them.
+///
+/// It is invoked inside the `impl` block, as `forward_transaction! { T, field; overrides }`.
+macro_rules! forward_transaction {
+    // Emits `$item` unless `$hook` is one of the overridden hooks in the brackets.
+    (@unless precondition [precondition $($other:ident)*] $item:tt) => {};
+    (@unless check [check $($other:ident)*] $item:tt) => {};
+    (@unless context [context $($other:ident)*] $item:tt) => {};
+    (@unless before [before $($other:ident)*] $item:tt) => {};
+    (@unless operation [operation $($other:ident)*] $item:tt) => {};
+    (@unless validate [validate $($other:ident)*] $item:tt) => {};
+    (@unless should_rollback [should_rollback $($other:ident)*] $item:tt) => {};
+    (@unless rollback [rollback $($other:ident)*] $item:tt) => {};
+    (@unless skip_finally [skip_finally $($other:ident)*] $item:tt) => {};
+    (@unless finally [finally $($other:ident)*] $item:tt) => {};
+    (@unless $hook:ident [$first:ident $($other:ident)*] $item:tt) => {
+        forward_transaction!(@unless $hook [$($other)*] $item);
+    };
+    (@unless $hook:ident [] { $($item:tt)* }) => {
+        $($item)*
+    };
+    ($inner:ty, $field:tt; $(fn $hook:ident $args:tt -> $ret:ty $body:block)*) => {
+        type BeforeError = <$inner as $crate::Transaction>::BeforeError;
+        type Ok = <$inner as $crate::Transaction>::Ok;
+        type Error = <$inner as $crate::Transaction>::Error;
+        type RollbackOk = <$inner as $crate::Transaction>::RollbackOk;
+        type RollbackError = <$inner as $crate::Transaction>::RollbackError;
+        type FinallyError = <$inner as $crate::Transaction>::FinallyError;
+
+        $(fn $hook $args -> $ret $body)*
+
+        forward_transaction!(@unless precondition [$($hook)*] {
+            fn precondition(&mut self) -> Result<(), Self::BeforeError> {
+                self.$field.precondition()
+            }
+        });
+        forward_transaction!(@unless check [$($hook)*] {
+            fn check(&self) -> Result<(), Self::BeforeError> {
+                self.$field.check()
+            }
+        });
+        forward_transaction!(@unless context [$($hook)*] {
+            fn context(&self) -> Option<String> {
+                self.$field.context()
+            }
+        });
+        forward_transaction!(@unless before [$($hook)*] {
+            fn before(&mut self) -> Result<(), Self::BeforeError> {
+                self.$field.before()
+            }
+        });
+        forward_transaction!(@unless operation [$($hook)*] {
+            fn operation(&mut self) -> Result<Self::Ok, Self::Error> {
+                self.$field.operation()
+            }
+        });
+        forward_transaction!(@unless validate [$($hook)*] {
+            fn validate(&mut self, ok: &Self::Ok) -> Result<(), Self::Error> {
+                self.$field.validate(ok)
+            }
+        });
+        forward_transaction!(@unless should_rollback [$($hook)*] {
+            fn should_rollback(&self, err: &Self::Error) -> bool {
+                self.$field.should_rollback(err)
+            }
+        });
+        forward_transaction!(@unless rollback [$($hook)*] {
+            fn rollback(
+                &mut self,
+                err: &Self::Error,
+            ) -> Result<Self::RollbackOk, Self::RollbackError> {
+                self.$field.rollback(err)
+            }
+        });
+        forward_transaction!(@unless skip_finally [$($hook)*] {
+            fn skip_finally(&self, state: &$crate::StateOf<Self>) -> bool {
+                self.$field.skip_finally(state)
+            }
+        });
+        forward_transaction!(@unless finally [$($hook)*] {
+            fn finally(&mut self, state: &$crate::StateOf<Self>) -> Result<(), Self::FinallyError> {
+                self.$field.finally(state)
+            }
+        });
+    };
+}
+
+pub(crate) use forward_transaction;
@@ -0,0 +1,126 @@
+use crate::rollback::{handle_drop_failure, private};
+use crate::{collapse_results, MaybeError, Rollback, RollbackGuard};
+
+/// A rollback that consists of several independent sub-actions.
+///
+/// This is like a [`crate::RollbackStack`] packaged as a single guard, which is convenient if
+/// all sub-actions are known up front. The sub-actions run in the order they are given in
+/// `rollback_actions`, and all of them run, even if some of them fail.
+///
+/// [`Rollback::do_rollback`] returns the results of all sub-actions in that order if all of
+/// them succeed, otherwise all errors in that order, like [`crate::RollbackStack`].
+/// [`ManyRollbackGuard::do_rollback_each`] returns the result of each sub-action instead. When
+/// the guard is dropped, each failure is handled separately, exactly like the failure of a
+/// dropped guard created via [`crate::rollback()`].
+///
+/// ```rust
+/// # use std::cell::RefCell;
+/// use transaction_rollback::{rollback_many, Rollback};
+///
+/// let log = RefCell::new(Vec::new());
+/// let remove = |name| {
+///     let log = &log;
+///     move || {
+///         log.borrow_mut().push(name);
+///         if name == "b" {
+///             Err(())
+///         } else {
+///             Ok(name)
+///         }
+///     }
+/// };
+///
+/// let guard = rollback_many(vec![remove("a"), remove("c")]);
+/// assert_eq!(Ok(vec!["a", "c"]), guard.do_rollback());
+///
+/// let guard = rollback_many(vec![remove("a"), remove("b")]);
+/// assert_eq!(Err(vec![()]), guard.do_rollback());
+///
+/// let guard = rollback_many(vec![remove("a"), remove("b")]);
+/// assert_eq!(vec![Ok("a"), Err(())], guard.do_rollback_each());
+///
+/// drop(rollback_many(vec![remove("c"), remove("d")]));
+/// assert_eq!(vec!["a", "c", "a", "b", "a", "b", "c", "d"], *log.borrow());
+/// ```
+pub fn rollback_many<'a, F, T, E>(rollback_actions: Vec<F>) -> ManyRollbackGuard<'a, T, E>
+where
+    F: FnOnce() -> Result<T, E> + 'a,
+    T: 'a,
+    E: MaybeError + 'a,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    ManyRollbackGuard {
+        rollback_actions: rollback_actions
+            .into_iter()
+            .map(|action| Box::new(action) as Box<dyn FnOnce() -> Result<T, E> + 'a>)
+            .collect(),
+    }
+}
+
+/// A rollback that consists of several independent sub-actions.
+///
+/// To create this and for more information see [`rollback_many`].
+pub struct ManyRollbackGuard<'a, T, E>
+where
+    T: 'a,
+    E: MaybeError + 'a,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    #[allow(clippy::type_complexity)]
+    rollback_actions: Vec<Box<dyn FnOnce() -> Result<T, E> + 'a>>,
+}
+
+impl<'a, T, E> ManyRollbackGuard<'a, T, E>
+where
+    T: 'a,
+    E: MaybeError + 'a,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    /// Runs all sub-actions, consuming the guard, and returns the result of each of them in
+    /// execution order.
+    pub fn do_rollback_each(mut self) -> Vec<Result<T, E>> {
+        self.rollback_actions
+            .drain(..)
+            .map(|action| action())
+            .collect()
+    }
+
+    /// Drops the rollback guard but does not run any of the sub-actions.
+    pub fn ok(mut self) {
+        self.rollback_actions.clear();
+    }
+}
+
+impl<'a, T, E> Rollback for ManyRollbackGuard<'a, T, E>
+where
+    T: 'a,
+    E: MaybeError + 'a,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    type RollbackOk = Vec<T>;
+    type RollbackError = Vec<E>;
+
+    /// Runs all sub-actions, consuming the guard.
+    ///
+    /// All sub-actions run, even if some of them fail. If all of them succeed, their results
+    /// are returned in execution order, otherwise all errors are returned in execution order.
+    fn do_rollback(self) -> Result<Vec<T>, Vec<E>> {
+        collapse_results(self.do_rollback_each())
+    }
+}
+
+impl<'a, T, E> Drop for ManyRollbackGuard<'a, T, E>
+where
+    T: 'a,
+    E: MaybeError + 'a,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    fn drop(&mut self) {
+        // Each failure is handled on its own, like the failure of a dropped guard.
+        for action in self.rollback_actions.drain(..) {
+            if let Err(err) = action() {
+                handle_drop_failure::<T, _>(err);
+            }
+        }
+    }
+}
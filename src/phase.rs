@@ -0,0 +1,30 @@
+/// The phases of executing a [`crate::Transaction`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TransactionPhase {
+    /// [`crate::Transaction::before`].
+    Before,
+    /// [`crate::Transaction::operation`].
+    Operation,
+    /// [`crate::Transaction::validate`].
+    Validate,
+    /// [`crate::Transaction::rollback`].
+    Rollback,
+    /// [`crate::Transaction::finally`].
+    Finally,
+}
+
+/// An error for one of the phases of a transaction.
+///
+/// Each variant holds the error type of the corresponding phase(s). See
+/// [`crate::Transaction::execute_unwind_checked_map`] for how it is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PhaseError<BE, E, RE, FE> {
+    /// An error for [`TransactionPhase::Before`].
+    Before(BE),
+    /// An error for [`TransactionPhase::Operation`] or [`TransactionPhase::Validate`].
+    Operation(E),
+    /// An error for [`TransactionPhase::Rollback`].
+    Rollback(RE),
+    /// An error for [`TransactionPhase::Finally`].
+    Finally(FE),
+}
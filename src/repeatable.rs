@@ -0,0 +1,232 @@
+use crate::rollback::{handle_drop_failure, private};
+use crate::{MaybeError, Rollback, RollbackGuard};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// A rollback whose action can be run more than once.
+///
+/// This works like [`rollback()`], but takes an [`FnMut`]. In addition to the usual ways of
+/// running the rollback (dropping the guard or calling [`Rollback::do_rollback`]), the action
+/// can be run via [`RepeatableRollbackGuard::do_rollback_keep`], which keeps the guard armed.
+///
+/// This is useful for retry loops: roll back, retry the operation, and roll back again if it
+/// failed again.
+///
+/// ```rust
+/// # use std::cell::Cell;
+/// use transaction_rollback::repeatable_rollback;
+///
+/// let rollbacks = Cell::new(0);
+/// let mut guard = repeatable_rollback(|| Ok::<_, ()>(rollbacks.set(rollbacks.get() + 1)));
+///
+/// for attempt in 0..3 {
+///     if attempt < 2 {
+///         // The attempt failed, roll back and try again.
+///         guard.do_rollback_keep().ok();
+///     } else {
+///         // The attempt succeeded.
+///         guard.ok();
+///         break;
+///     }
+/// }
+/// assert_eq!(2, rollbacks.get());
+/// ```
+pub fn repeatable_rollback<'a, F, T, E>(rollback_action: F) -> RepeatableRollbackGuard<'a, T, E>
+where
+    F: FnMut() -> Result<T, E> + 'a,
+    T: 'a,
+    E: MaybeError + 'a,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    RepeatableRollbackGuard {
+        rollback_action: Some(Box::new(rollback_action)),
+    }
+}
+
+/// A rollback whose action can be run more than once.
+///
+/// To create this and for more information see [`repeatable_rollback`].
+pub struct RepeatableRollbackGuard<'a, T, E>
+where
+    T: 'a,
+    E: MaybeError + 'a,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    rollback_action: Option<Box<dyn FnMut() -> Result<T, E> + 'a>>,
+}
+
+impl<'a, T, E> RepeatableRollbackGuard<'a, T, E>
+where
+    T: 'a,
+    E: MaybeError + 'a,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    /// Runs the rollback, but keeps the guard armed.
+    ///
+    /// The action will run again when the guard is dropped or rolled back, and can be run
+    /// again with this method any number of times. To stop this, dismiss the guard with
+    /// [`Self::ok`] once no further rollback is wanted.
+    pub fn do_rollback_keep(&mut self) -> Result<T, E> {
+        match self.rollback_action.as_mut() {
+            Some(action) => action(),
+            None => unreachable!("the action is only taken when the guard is consumed"),
+        }
+    }
+
+    /// Drops the rollback guard but does not run the rollback function.
+    pub fn ok(mut self) {
+        self.rollback_action.take();
+    }
+}
+
+impl<'a, T, E> Rollback for RepeatableRollbackGuard<'a, T, E>
+where
+    T: 'a,
+    E: MaybeError + 'a,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    type RollbackOk = T;
+    type RollbackError = E;
+
+    /// Performs the rollback, consuming the guard.
+    fn do_rollback(mut self) -> Result<T, E> {
+        match self.rollback_action.take() {
+            Some(mut action) => action(),
+            None => unreachable!("the action is only taken when the guard is consumed"),
+        }
+    }
+}
+
+impl<'a, T, E> Drop for RepeatableRollbackGuard<'a, T, E>
+where
+    T: 'a,
+    E: MaybeError + 'a,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    fn drop(&mut self) {
+        // A failure is handled like the failure of a dropped guard.
+        if let Some(mut action) = self.rollback_action.take() {
+            if let Err(err) = action() {
+                handle_drop_failure::<T, _>(err);
+            }
+        }
+    }
+}
+
+/// A rollback whose action can be run more than once, but at most `limit` times.
+///
+/// This works like [`repeatable_rollback`], but counts how often the action ran, including
+/// the run when the guard is dropped or rolled back via [`Rollback::do_rollback`]. Once it ran
+/// `limit` times, the guard is disarmed: Further calls to
+/// [`LimitedRollbackGuard::do_rollback_keep`] or [`Rollback::do_rollback`] return
+/// [`LimitExceeded`] and the action is not run on drop anymore.
+///
+/// This protects against loops that accidentally roll back too many times.
+///
+/// ```rust
+/// # use std::cell::Cell;
+/// use transaction_rollback::{repeatable_rollback_limited, LimitExceeded};
+///
+/// let rollbacks = Cell::new(0);
+/// let mut guard =
+///     repeatable_rollback_limited(2, || Ok::<_, ()>(rollbacks.set(rollbacks.get() + 1)));
+///
+/// assert_eq!(Ok(Ok(())), guard.do_rollback_keep());
+/// assert_eq!(Ok(Ok(())), guard.do_rollback_keep());
+/// assert_eq!(Err(LimitExceeded), guard.do_rollback_keep());
+/// // The action does not run on drop either.
+/// drop(guard);
+/// assert_eq!(2, rollbacks.get());
+/// ```
+pub fn repeatable_rollback_limited<'a, F, T, E>(
+    limit: usize,
+    rollback_action: F,
+) -> LimitedRollbackGuard<'a, T, E>
+where
+    F: FnMut() -> Result<T, E> + 'a,
+    T: 'a,
+    E: MaybeError + 'a,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    LimitedRollbackGuard {
+        guard: (limit > 0).then(|| repeatable_rollback(rollback_action)),
+        remaining: limit,
+    }
+}
+
+/// A rollback whose action can be run a limited number of times.
+///
+/// To create this and for more information see [`repeatable_rollback_limited`].
+pub struct LimitedRollbackGuard<'a, T, E>
+where
+    T: 'a,
+    E: MaybeError + 'a,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    // `None` once the limit is reached.
+    guard: Option<RepeatableRollbackGuard<'a, T, E>>,
+    remaining: usize,
+}
+
+impl<'a, T, E> LimitedRollbackGuard<'a, T, E>
+where
+    T: 'a,
+    E: MaybeError + 'a,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    /// Runs the rollback, but keeps the guard armed, see
+    /// [`RepeatableRollbackGuard::do_rollback_keep`].
+    ///
+    /// If this is the last run allowed by the limit, the guard is disarmed afterwards. If the
+    /// limit was already reached, the action is not run and [`LimitExceeded`] is returned.
+    pub fn do_rollback_keep(&mut self) -> Result<Result<T, E>, LimitExceeded> {
+        let guard = self.guard.take().ok_or(LimitExceeded)?;
+        self.remaining -= 1;
+        if self.remaining == 0 {
+            Ok(guard.do_rollback())
+        } else {
+            Ok(self.guard.insert(guard).do_rollback_keep())
+        }
+    }
+
+    /// Returns how many more times the action may run.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// Drops the rollback guard but does not run the rollback function.
+    pub fn ok(self) {
+        if let Some(guard) = self.guard {
+            guard.ok();
+        }
+    }
+}
+
+impl<'a, T, E> Rollback for LimitedRollbackGuard<'a, T, E>
+where
+    T: 'a,
+    E: MaybeError + 'a,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    type RollbackOk = Result<T, E>;
+    type RollbackError = LimitExceeded;
+
+    /// Performs the rollback, consuming the guard.
+    ///
+    /// Returns [`LimitExceeded`] without running the action if the limit was already reached.
+    fn do_rollback(self) -> Result<Result<T, E>, LimitExceeded> {
+        Ok(self.guard.ok_or(LimitExceeded)?.do_rollback())
+    }
+}
+
+/// The error of a [`LimitedRollbackGuard`] whose action already ran the maximum number of times.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitExceeded;
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The rollback already ran the maximum number of times")
+    }
+}
+
+impl Error for LimitExceeded {}
@@ -0,0 +1,158 @@
+use crate::TransactionState;
+use std::fmt::{Debug, Display, Formatter};
+
+/// A summary of the states of many transactions, for example of a batch.
+///
+/// States are added via [`TransactionReport::record`], or by collecting them into a report.
+/// The report counts them by outcome, with the same categories as
+/// [`TransactionState::exit_code`]:
+///
+/// - [`TransactionReport::successes`]: Transactions that succeeded or were skipped.
+/// - [`TransactionReport::rollbacks`]: Transactions whose operation failed and that were rolled
+///   back successfully, even if their `finally` failed.
+/// - [`TransactionReport::failures`]: All other transactions, that is those whose `before`,
+///   rollback or `finally` failed, or that were not rolled back.
+///
+/// If all types of the states implement [`Debug`], the report implements [`Display`] with a
+/// summary of the counts, followed by a line for each transaction that did not succeed.
+///
+/// ```rust
+/// use transaction_rollback::{TransactionReport, TransactionState};
+///
+/// let states: [TransactionState<(), u8, &str, (), &str, ()>; 3] = [
+///     TransactionState::Ok(1),
+///     TransactionState::Rollback("upload failed", Ok(())),
+///     TransactionState::Rollback("upload failed", Err("can not delete file")),
+/// ];
+/// let mut report = TransactionReport::new();
+/// for state in states {
+///     report.record(state);
+/// }
+///
+/// assert_eq!(1, report.successes());
+/// assert_eq!(1, report.rollbacks());
+/// assert_eq!(1, report.failures());
+/// assert!(!report.overall_ok());
+/// assert_eq!(
+///     "3 transactions: 1 succeeded, 1 rolled back, 1 failed\n\
+///      - #1: Rollback(\"upload failed\", Ok(()))\n\
+///      - #2: Rollback(\"upload failed\", Err(\"can not delete file\"))",
+///     report.to_string()
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionReport<BE, O, E, RO, RE, FE> {
+    states: Vec<TransactionState<BE, O, E, RO, RE, FE>>,
+}
+
+impl<BE, O, E, RO, RE, FE> TransactionReport<BE, O, E, RO, RE, FE> {
+    /// Creates an empty report.
+    pub fn new() -> Self {
+        Self { states: Vec::new() }
+    }
+
+    /// Adds the state of a transaction to the report.
+    pub fn record(&mut self, state: TransactionState<BE, O, E, RO, RE, FE>) {
+        self.states.push(state);
+    }
+
+    /// The number of transactions that succeeded or were skipped.
+    pub fn successes(&self) -> usize {
+        self.count(ExitCodes::EXIT_SUCCESS)
+    }
+
+    /// The number of transactions that were rolled back successfully.
+    pub fn rollbacks(&self) -> usize {
+        self.count(ExitCodes::EXIT_ROLLBACK)
+    }
+
+    /// The number of transactions that neither succeeded nor were rolled back successfully.
+    pub fn failures(&self) -> usize {
+        self.len() - self.successes() - self.rollbacks()
+    }
+
+    /// Returns `true` if all transactions succeeded or were skipped. This is also the case if
+    /// no transaction was recorded.
+    pub fn overall_ok(&self) -> bool {
+        self.successes() == self.len()
+    }
+
+    /// The recorded states, in the order they were recorded.
+    pub fn states(&self) -> &[TransactionState<BE, O, E, RO, RE, FE>] {
+        &self.states
+    }
+
+    /// Iterates over the states of all transactions that neither succeeded nor were skipped, in
+    /// the order they were recorded.
+    pub fn unsuccessful(&self) -> impl Iterator<Item = &TransactionState<BE, O, E, RO, RE, FE>> {
+        self.states
+            .iter()
+            .filter(|state| state.exit_code() != ExitCodes::EXIT_SUCCESS)
+    }
+
+    /// The number of recorded transactions.
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Returns `true` if no transaction was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    fn count(&self, exit_code: i32) -> usize {
+        self.states
+            .iter()
+            .filter(|state| state.exit_code() == exit_code)
+            .count()
+    }
+}
+
+impl<BE, O, E, RO, RE, FE> Default for TransactionReport<BE, O, E, RO, RE, FE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<BE, O, E, RO, RE, FE> Extend<TransactionState<BE, O, E, RO, RE, FE>>
+    for TransactionReport<BE, O, E, RO, RE, FE>
+{
+    fn extend<I: IntoIterator<Item = TransactionState<BE, O, E, RO, RE, FE>>>(&mut self, iter: I) {
+        self.states.extend(iter);
+    }
+}
+
+impl<BE, O, E, RO, RE, FE> FromIterator<TransactionState<BE, O, E, RO, RE, FE>>
+    for TransactionReport<BE, O, E, RO, RE, FE>
+{
+    fn from_iter<I: IntoIterator<Item = TransactionState<BE, O, E, RO, RE, FE>>>(iter: I) -> Self {
+        Self {
+            states: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<BE, O, E, RO, RE, FE> Display for TransactionReport<BE, O, E, RO, RE, FE>
+where
+    TransactionState<BE, O, E, RO, RE, FE>: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} transactions: {} succeeded, {} rolled back, {} failed",
+            self.len(),
+            self.successes(),
+            self.rollbacks(),
+            self.failures()
+        )?;
+        for (index, state) in self.states.iter().enumerate() {
+            if state.exit_code() != ExitCodes::EXIT_SUCCESS {
+                write!(f, "\n- #{index}: {state:?}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// The exit codes are the same for all types of states.
+type ExitCodes = TransactionState<(), (), (), (), (), ()>;
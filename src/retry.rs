@@ -0,0 +1,208 @@
+use crate::{StateOf, Transaction, TransactionState};
+
+/// A transaction that is retried as a whole if it fails.
+///
+/// [`Retry::execute`] executes a fresh clone of the transaction, including [`Transaction::before`]
+/// and [`Transaction::finally`], until an attempt's operation succeeds or the maximum number of
+/// attempts is reached. This is useful if not only the operation, but also the setup is flaky,
+/// so that both have to be repeated together.
+///
+/// An attempt failed if its state is [`TransactionState::FailedBefore`],
+/// [`TransactionState::Rollback`], [`TransactionState::FailedNoRollback`] or one of their
+/// `...ButFailedFinally` variants. Each failed attempt is fully completed, including its
+/// rollback and `finally`, before the next one starts. [`TransactionState::OkButFailedFinally`]
+/// is not retried, since the operation already succeeded.
+///
+/// The transaction must implement [`Clone`], since [`Transaction::execute`] consumes it. Each
+/// attempt executes a clone of the original transaction, so state changed by one attempt is
+/// not visible to the next one, unless it is shared, for example via an [`std::rc::Rc`].
+///
+/// ```rust
+/// # use std::cell::Cell;
+/// # use std::rc::Rc;
+/// use transaction_rollback::{Retry, Transaction, TransactionState};
+/// # type State = TransactionState<&'static str, u32, (), (), (), ()>;
+///
+/// #[derive(Clone)]
+/// struct Connect {
+///     // Shared between all attempts.
+///     attempts: Rc<Cell<u32>>,
+/// }
+///
+/// impl Transaction for Connect {
+///     type BeforeError = &'static str;
+///     type Ok = u32;
+///     // ...
+/// #     type Error = ();
+/// #     type RollbackOk = ();
+/// #     type RollbackError = ();
+/// #     type FinallyError = ();
+///
+///     fn before(&mut self) -> Result<(), &'static str> {
+///         self.attempts.set(self.attempts.get() + 1);
+///         if self.attempts.get() < 3 { Err("flaky") } else { Ok(()) }
+///     }
+///
+///     fn operation(&mut self) -> Result<u32, ()> {
+///         Ok(self.attempts.get())
+///     }
+///
+/// #     fn rollback(&mut self, _: &()) -> Result<(), ()> { Ok(()) }
+/// #     fn finally(&mut self, _: &State) -> Result<(), ()> { Ok(()) }
+///     // ...
+/// }
+///
+/// let connect = Connect { attempts: Rc::new(Cell::new(0)) };
+/// assert_eq!(TransactionState::Ok(3), Retry::new(connect.clone(), 5).execute());
+///
+/// connect.attempts.set(0);
+/// assert_eq!(TransactionState::FailedBefore("flaky"), Retry::new(connect, 2).execute());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Retry<T> {
+    transaction: T,
+    max_attempts: usize,
+}
+
+impl<T> Retry<T>
+where
+    T: Transaction + Clone,
+{
+    /// Wraps `transaction`, to be executed at most `max_attempts` times.
+    ///
+    /// The transaction is always executed at least once, even if `max_attempts` is `0`.
+    pub fn new(transaction: T, max_attempts: usize) -> Self {
+        Self {
+            transaction,
+            max_attempts,
+        }
+    }
+
+    /// Executes the transaction until an attempt succeeds or the maximum number of attempts is
+    /// reached, and returns the state of the last attempt.
+    ///
+    /// # Panics
+    /// If executing the transaction panics, see [`Transaction::execute`].
+    pub fn execute(self) -> StateOf<T> {
+        let mut state = self.transaction.clone().execute();
+        for _ in 1..self.max_attempts {
+            if !failed(&state) {
+                break;
+            }
+            state = self.transaction.clone().execute();
+        }
+        state
+    }
+
+    /// Like [`Self::execute`], but stops retrying if [`Transaction::before`] failed with an
+    /// error that is not retryable, see [`RetryableError`].
+    ///
+    /// This requires the `BeforeError` of the transaction to implement [`RetryableError`].
+    /// [`Self::execute`] retries all failures, which is the same as all before errors being
+    /// retryable.
+    ///
+    /// ```rust
+    /// # use std::cell::Cell;
+    /// # use std::rc::Rc;
+    /// use transaction_rollback::{Retry, RetryableError, Transaction, TransactionState};
+    /// # type State = TransactionState<ConnectError, (), (), (), (), ()>;
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// enum ConnectError {
+    ///     Timeout,
+    ///     InvalidCredentials,
+    /// }
+    ///
+    /// impl RetryableError for ConnectError {
+    ///     fn is_retryable(&self) -> bool {
+    ///         *self == ConnectError::Timeout
+    ///     }
+    /// }
+    ///
+    /// #[derive(Clone)]
+    /// struct Connect {
+    ///     attempts: Rc<Cell<u32>>,
+    ///     error: fn() -> ConnectError,
+    /// }
+    ///
+    /// impl Transaction for Connect {
+    ///     type BeforeError = ConnectError;
+    ///     // ...
+    /// #     type Ok = ();
+    /// #     type Error = ();
+    /// #     type RollbackOk = ();
+    /// #     type RollbackError = ();
+    /// #     type FinallyError = ();
+    ///
+    ///     fn before(&mut self) -> Result<(), ConnectError> {
+    ///         self.attempts.set(self.attempts.get() + 1);
+    ///         Err((self.error)())
+    ///     }
+    ///
+    /// #     fn operation(&mut self) -> Result<(), ()> { Ok(()) }
+    /// #     fn rollback(&mut self, _: &()) -> Result<(), ()> { Ok(()) }
+    /// #     fn finally(&mut self, _: &State) -> Result<(), ()> { Ok(()) }
+    ///     // ...
+    /// }
+    ///
+    /// let attempts = Rc::new(Cell::new(0));
+    /// let timeout = Connect { attempts: attempts.clone(), error: || ConnectError::Timeout };
+    /// Retry::new(timeout, 3).execute_retryable();
+    /// assert_eq!(3, attempts.get());
+    ///
+    /// attempts.set(0);
+    /// let invalid = Connect {
+    ///     attempts: attempts.clone(),
+    ///     error: || ConnectError::InvalidCredentials,
+    /// };
+    /// Retry::new(invalid, 3).execute_retryable();
+    /// assert_eq!(1, attempts.get());
+    /// ```
+    pub fn execute_retryable(self) -> StateOf<T>
+    where
+        T::BeforeError: RetryableError,
+    {
+        let mut state = self.transaction.clone().execute();
+        for _ in 1..self.max_attempts {
+            if !failed(&state) || state.before_error_is_retryable() == Some(false) {
+                break;
+            }
+            state = self.transaction.clone().execute();
+        }
+        state
+    }
+}
+
+/// An error that knows whether the failed action may succeed if it is retried.
+///
+/// This is used for the `BeforeError` of transactions by [`Retry::execute_retryable`] and
+/// [`TransactionState::before_error_is_retryable`], to tell transient failures, after which
+/// the caller may retry, apart from fatal ones. Where a before error does not implement this
+/// trait, it is treated as retryable.
+pub trait RetryableError {
+    /// Returns `true` if the failed action may succeed if it is retried.
+    fn is_retryable(&self) -> bool;
+}
+
+impl<BE, O, E, RO, RE, FE> TransactionState<BE, O, E, RO, RE, FE>
+where
+    BE: RetryableError,
+{
+    /// Whether the error of [`TransactionState::FailedBefore`] is retryable, see
+    /// [`RetryableError`]. `None` for all other variants.
+    pub fn before_error_is_retryable(&self) -> Option<bool> {
+        match self {
+            TransactionState::FailedBefore(be) => Some(be.is_retryable()),
+            _ => None,
+        }
+    }
+}
+
+fn failed<BE, O, E, RO, RE, FE>(state: &TransactionState<BE, O, E, RO, RE, FE>) -> bool {
+    !matches!(
+        state,
+        TransactionState::Ok(_)
+            | TransactionState::OkButFailedFinally(_, _)
+            | TransactionState::Skipped(_)
+    )
+}
@@ -1,11 +1,20 @@
+use crate::{LinkedGuards, PanicError, TransactionState, ZippedGuard};
+#[cfg(feature = "serde")]
+use serde::ser::{Serialize, SerializeStruct, Serializer};
 use std::error::Error;
 use std::fmt::{Display, Formatter};
+use std::future::{poll_fn, Future};
 use std::marker::PhantomData;
 use std::mem;
 use std::mem::{ManuallyDrop, MaybeUninit};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::MutexGuard;
+use std::task::Poll;
 use try_drop::adapters::{
     FallbackTryDropStrategyHandler, FallibleTryDropStrategyRef, TryDropStrategyRef,
 };
+use try_drop::handlers::primary;
 use try_drop::{ImpureTryDrop as TryDrop, PureTryDrop, TryDropStrategy};
 
 /// A rollback for a transaction.
@@ -36,13 +45,188 @@ where
     F: FnOnce() -> Result<T, E> + 'a,
     E: MaybeError,
     RollbackGuard<'a, T, E>: private::DropLike,
+{
+    rollback_with_trigger(move |_| rollback_action())
+}
+
+/// What triggered a rollback, see [`rollback_with_trigger`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RollbackTrigger {
+    /// The rollback was run explicitly via [`Rollback::do_rollback`].
+    Explicit,
+    /// The rollback was run because the guard was dropped. This includes guards dropped
+    /// while unwinding from a panic.
+    Drop,
+}
+
+/// A rollback whose action is told what triggered it.
+///
+/// This works like [`rollback()`], but `rollback_action` is passed
+/// [`RollbackTrigger::Explicit`] if the rollback is run via [`Rollback::do_rollback`], and
+/// [`RollbackTrigger::Drop`] if it is run because the guard is dropped, including during
+/// unwinding. This allows the action to behave or log differently depending on the trigger.
+///
+/// ```rust
+/// # use std::cell::RefCell;
+/// use transaction_rollback::{rollback_with_trigger, Rollback, RollbackTrigger};
+///
+/// let triggers = RefCell::new(Vec::new());
+/// let guard = || {
+///     rollback_with_trigger(|trigger| Ok::<_, ()>(triggers.borrow_mut().push(trigger)))
+/// };
+///
+/// guard().do_rollback().unwrap();
+/// drop(guard());
+/// assert_eq!(
+///     vec![RollbackTrigger::Explicit, RollbackTrigger::Drop],
+///     *triggers.borrow()
+/// );
+/// ```
+pub fn rollback_with_trigger<'a, F, T, E>(rollback_action: F) -> RollbackGuard<'a, T, E>
+where
+    F: FnOnce(RollbackTrigger) -> Result<T, E> + 'a,
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
 {
     RollbackGuard {
         rollback_action: MaybeUninit::new(Box::new(rollback_action)),
+        armed: true,
+        mandatory: false,
         _error_type: PhantomData,
     }
 }
 
+/// The result of a rollback that distinguishes undoing something from having nothing to undo,
+/// see [`rollback_with_outcome`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RollbackOutcome<T> {
+    /// The rollback undid something, with the result of undoing it.
+    Undone(T),
+    /// There was nothing to undo, for example because the resource was never created.
+    NoOp,
+}
+
+impl<T> RollbackOutcome<T> {
+    /// Returns `true` if there was nothing to undo.
+    pub fn is_noop(&self) -> bool {
+        matches!(self, RollbackOutcome::NoOp)
+    }
+
+    /// Returns the result of undoing, if something was undone.
+    pub fn undone(self) -> Option<T> {
+        match self {
+            RollbackOutcome::Undone(v) => Some(v),
+            RollbackOutcome::NoOp => None,
+        }
+    }
+}
+
+/// A rollback whose action reports whether it undid something or had nothing to undo.
+///
+/// This is the same as [`rollback()`] with a [`RollbackOutcome`] as success type. The outcome
+/// is returned by [`Rollback::do_rollback`], which allows telling real compensations apart
+/// from no-ops, for example for monitoring. When the guard is dropped instead, the outcome is
+/// discarded like any other success value; only failures are handled.
+///
+/// ```rust
+/// use transaction_rollback::{rollback_with_outcome, Rollback, RollbackOutcome};
+///
+/// let created: Option<&str> = None;
+/// let guard = rollback_with_outcome::<_, _, ()>(move || match created {
+///     Some(path) => Ok(RollbackOutcome::Undone(path)),
+///     None => Ok(RollbackOutcome::NoOp),
+/// });
+/// assert_eq!(Ok(RollbackOutcome::NoOp), guard.do_rollback());
+/// ```
+pub fn rollback_with_outcome<'a, F, T, E>(
+    rollback_action: F,
+) -> RollbackGuard<'a, RollbackOutcome<T>, E>
+where
+    F: FnOnce() -> Result<RollbackOutcome<T>, E> + 'a,
+    E: MaybeError,
+    RollbackGuard<'a, RollbackOutcome<T>, E>: private::DropLike,
+{
+    rollback(rollback_action)
+}
+
+/// A rollback that is disarmed until [`RollbackGuard::arm`] is called.
+///
+/// This works like [`rollback()`], but dropping the returned guard does nothing while it is
+/// disarmed: The action is discarded without running. This allows registering a cleanup up
+/// front and only activating it once the resource it cleans up was actually acquired. A guard
+/// can be disarmed again via [`RollbackGuard::disarm`].
+///
+/// Arming only applies to dropping. [`Rollback::do_rollback`] is an explicit request and always
+/// runs the action, armed or not.
+///
+/// ```rust
+/// # use std::cell::Cell;
+/// use transaction_rollback::rollback_disarmed;
+///
+/// let released = Cell::new(0);
+/// let acquire = |succeed: bool| {
+///     let mut guard =
+///         rollback_disarmed(|| Ok::<_, ()>(released.set(released.get() + 1)));
+///     if succeed {
+///         // ... acquire the resource ...
+///         guard.arm();
+///     }
+/// };
+///
+/// acquire(false);
+/// assert_eq!(0, released.get());
+/// acquire(true);
+/// assert_eq!(1, released.get());
+/// ```
+pub fn rollback_disarmed<'a, F, T, E>(rollback_action: F) -> RollbackGuard<'a, T, E>
+where
+    F: FnOnce() -> Result<T, E> + 'a,
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    let mut guard = rollback(rollback_action);
+    guard.disarm();
+    guard
+}
+
+/// A rollback that may fail, like [`rollback()`], that checks that a [`TryDropStrategy`] is
+/// configured.
+///
+/// In debug builds this panics if [`drop_strategy_is_configured`] returns `false`, to catch
+/// guards whose failures would not be handled as intended. In release builds this is identical
+/// to [`rollback()`].
+pub fn rollback_checked<'a, F, T, E>(rollback_action: F) -> RollbackGuard<'a, T, RollbackError<E>>
+where
+    F: FnOnce() -> Result<T, RollbackError<E>> + 'a,
+    E: Error + Send + Sync + 'static,
+{
+    debug_assert!(
+        drop_strategy_is_configured(),
+        "transaction-rollback: a fallible rollback guard was created, but no try_drop strategy \
+         is installed. Install one via `try_drop::install_global_handlers` or \
+         `try_drop::install_thread_local_handlers`."
+    );
+    rollback(rollback_action)
+}
+
+/// Returns whether a primary [`TryDropStrategy`] is installed, either globally or for the
+/// current thread.
+///
+/// Failures of rollback guards created via [`rollback()`] are passed to this strategy when the
+/// guard is dropped. If none is installed, `try_drop` falls back to its default behaviour.
+///
+/// ```rust
+/// # use try_drop::drop_strategies::NoOpDropStrategy;
+/// use transaction_rollback::drop_strategy_is_configured;
+///
+/// assert!(!drop_strategy_is_configured());
+/// try_drop::install_thread_local_handlers(NoOpDropStrategy, NoOpDropStrategy);
+/// assert!(drop_strategy_is_configured());
+/// ```
+pub fn drop_strategy_is_configured() -> bool {
+    primary::global::try_read().is_ok() || primary::thread_local::try_read(|_| ()).is_ok()
+}
+
 /// A rollback that can not fail.
 ///
 /// See [`rollback`] for more information.
@@ -59,6 +243,74 @@ where
     rollback(Box::new(|| Ok(rollback_action())))
 }
 
+/// A rollback that drops `value`.
+///
+/// This is useful to integrate values that clean up via their own [`Drop`] implementation,
+/// for example guards from other libraries, with the rollbacks of this crate: The value is
+/// dropped exactly when the returned guard is dropped or rolled back, so it can be put
+/// into a [`crate::RollbackStack`] to order it relative to other rollbacks. Calling
+/// [`RollbackGuard::ok`] on the guard leaks the value instead of dropping it.
+///
+/// ```rust
+/// # use std::cell::Cell;
+/// use transaction_rollback::{rollback_dropping, Rollback};
+///
+/// thread_local!(static DROPPED: Cell<bool> = const { Cell::new(false) });
+///
+/// struct ForeignGuard;
+/// impl Drop for ForeignGuard {
+///     fn drop(&mut self) {
+///         DROPPED.set(true);
+///     }
+/// }
+///
+/// let guard = rollback_dropping(ForeignGuard);
+/// assert!(!DROPPED.get());
+/// guard.do_rollback().unwrap();
+/// assert!(DROPPED.get());
+/// ```
+pub fn rollback_dropping<T>(value: T) -> RollbackGuard<'static, (), ()>
+where
+    T: 'static,
+{
+    infallible_rollback(move || drop(value))
+}
+
+/// A rollback that releases a lock, by dropping its guard.
+///
+/// This works like [`rollback_dropping`], but for [`MutexGuard`]s, which borrow their
+/// [`std::sync::Mutex`]. It allows controlling when the lock is released relative to other
+/// rollbacks, for example in a [`crate::RollbackStack`].
+///
+/// ```rust
+/// # use std::cell::RefCell;
+/// use std::sync::Mutex;
+/// use transaction_rollback::{infallible_rollback, rollback_unlock, RollbackStack};
+///
+/// let files = Mutex::new(vec!["a.txt"]);
+/// let deleted = RefCell::new(Vec::new());
+///
+/// let mut stack = RollbackStack::default();
+/// let mut guard = files.lock().unwrap();
+/// guard.push("b.txt");
+/// stack.push(rollback_unlock(guard));
+/// stack.push(infallible_rollback(|| {
+///     // The lock is still held here, since the stack rolls back in reverse order.
+///     assert!(files.try_lock().is_err());
+///     deleted.borrow_mut().push("b.txt");
+/// }));
+/// drop(stack);
+///
+/// assert_eq!(vec!["b.txt"], *deleted.borrow());
+/// assert!(files.try_lock().is_ok());
+/// ```
+pub fn rollback_unlock<'a, T>(guard: MutexGuard<'a, T>) -> RollbackGuard<'a, (), ()>
+where
+    T: ?Sized,
+{
+    infallible_rollback(move || drop(guard))
+}
+
 /// Either a [`RollbackError`] with an inner [`Error`] or `()`.
 pub trait MaybeError {}
 
@@ -72,6 +324,65 @@ pub struct RollbackError<E>(pub E)
 where
     E: Send + Sync + 'static;
 
+impl<E> RollbackError<E>
+where
+    E: Send + Sync + 'static,
+{
+    /// Returns a reference to the inner error.
+    pub fn inner(&self) -> &E {
+        &self.0
+    }
+
+    /// Consumes this error and returns the inner error.
+    pub fn into_inner(self) -> E {
+        self.0
+    }
+
+    /// Attaches `source` as the cause of this error, for example the error of the operation
+    /// that made the rollback necessary.
+    ///
+    /// The source is returned by [`Error::source`], so it is included when walking the chain of
+    /// errors. The inner error is wrapped in [`WithSource`], which displays the same as the
+    /// inner error.
+    ///
+    /// ```rust
+    /// use std::error::Error;
+    /// use transaction_rollback::RollbackError;
+    ///
+    /// #[derive(Debug, thiserror::Error)]
+    /// #[error("{0}")]
+    /// struct ExampleError(&'static str);
+    ///
+    /// let err = RollbackError(ExampleError("can not delete file"))
+    ///     .with_source(Box::new(ExampleError("upload failed")));
+    /// assert_eq!("Rollback error: can not delete file", err.to_string());
+    /// assert_eq!("upload failed", err.source().unwrap().to_string());
+    /// ```
+    pub fn with_source(self, source: Box<dyn Error + Send + Sync>) -> RollbackError<WithSource<E>> {
+        RollbackError(WithSource {
+            inner: self.0,
+            source,
+        })
+    }
+}
+
+/// Serializes the error as a struct `RollbackError` with a single field `error`, containing the
+/// inner error. In JSON, this looks like `{"error": ...}`. To include the chain of sources,
+/// attach the source via [`RollbackError::with_source`], see [`WithSource`].
+///
+/// Only available with the `serde` feature.
+#[cfg(feature = "serde")]
+impl<E> Serialize for RollbackError<E>
+where
+    E: Serialize + Send + Sync + 'static,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("RollbackError", 1)?;
+        state.serialize_field("error", &self.0)?;
+        state.end()
+    }
+}
+
 impl<E> Display for RollbackError<E>
 where
     E: Display + Send + Sync + 'static,
@@ -81,7 +392,77 @@ where
     }
 }
 
-impl<E> Error for RollbackError<E> where E: Error + Send + Sync + 'static {}
+impl<E> Error for RollbackError<E>
+where
+    E: Error + Send + Sync + 'static,
+{
+    // The inner error is already part of the message, so its source is returned instead.
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.0.source()
+    }
+}
+
+/// An error with an attached source, see [`RollbackError::with_source`].
+#[derive(Debug)]
+pub struct WithSource<E> {
+    inner: E,
+    source: Box<dyn Error + Send + Sync>,
+}
+
+impl<E> WithSource<E> {
+    /// Returns a reference to the inner error.
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    /// Consumes this error and returns the inner error, discarding the source.
+    pub fn into_inner(self) -> E {
+        self.inner
+    }
+}
+
+/// Serializes the error as a struct `WithSource` with the fields `inner`, containing the inner
+/// error, and `source`, containing the [`Display`] representation of the attached source and of
+/// all further sources in the chain of [`Error::source`], in that order. In JSON, this looks like
+/// `{"inner": ..., "source": ["upload failed", "connection reset"]}`.
+///
+/// Only available with the `serde` feature.
+#[cfg(feature = "serde")]
+impl<E> Serialize for WithSource<E>
+where
+    E: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut chain = Vec::new();
+        let mut next: Option<&(dyn Error + 'static)> = Some(&*self.source);
+        while let Some(err) = next {
+            chain.push(err.to_string());
+            next = err.source();
+        }
+        let mut state = serializer.serialize_struct("WithSource", 2)?;
+        state.serialize_field("inner", &self.inner)?;
+        state.serialize_field("source", &chain)?;
+        state.end()
+    }
+}
+
+impl<E> Display for WithSource<E>
+where
+    E: Display,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl<E> Error for WithSource<E>
+where
+    E: Error,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.source)
+    }
+}
 
 /// Trait for a type that can be rolled back.
 pub trait Rollback {
@@ -100,7 +481,12 @@ pub struct RollbackGuard<'a, T, E>
 where
     Self: private::DropLike + 'a,
 {
-    rollback_action: MaybeUninit<Box<dyn FnOnce() -> Result<T, E> + 'a>>,
+    #[allow(clippy::type_complexity)]
+    rollback_action: MaybeUninit<Box<dyn FnOnce(RollbackTrigger) -> Result<T, E> + 'a>>,
+    /// Whether the rollback runs on drop, see [`rollback_disarmed`].
+    armed: bool,
+    /// Whether this guard is wrapped in a [`MandatoryRollbackGuard`].
+    mandatory: bool,
     _error_type: PhantomData<E>,
 }
 
@@ -117,7 +503,7 @@ where
         let mut slf = ManuallyDrop::new(self);
         // SAFETY: Since we do not drop `Self` (because of the `ManuallyDrop`) its `Drop` code
         // will not run, and thus the call below will be the only call to `_do_rollback`.
-        unsafe { slf._do_rollback() }
+        unsafe { slf._do_rollback(RollbackTrigger::Explicit) }
     }
 }
 
@@ -132,19 +518,346 @@ where
         mem::forget(self);
     }
 
+    /// Arms the guard, so that it rolls back when it is dropped. Guards are armed unless created
+    /// via [`rollback_disarmed`].
+    pub fn arm(&mut self) {
+        self.armed = true;
+    }
+
+    /// Disarms the guard, so that dropping it discards the rollback action without running it.
+    /// Unlike [`Self::ok`], the guard can be armed again later.
+    pub fn disarm(&mut self) {
+        self.armed = false;
+    }
+
+    /// Whether the guard rolls back when it is dropped.
+    pub fn is_armed(&self) -> bool {
+        self.armed
+    }
+
     /// Makes the rollback mandatory, by returning a type that wraps this guard, implements
     /// [`Rollback`] as well but does not provide [`Self::ok`]. Note that the returned
     /// wrapped guard can still be prevented from executing on [`Drop`] by using
-    /// functionality like [`mem::forget`].
-    pub fn mandatory(self) -> MandatoryRollbackGuard<'a, T, E> {
+    /// functionality like [`mem::forget`]. A disarmed guard is armed by this.
+    pub fn mandatory(mut self) -> MandatoryRollbackGuard<'a, T, E> {
+        self.armed = true;
+        self.mandatory = true;
         MandatoryRollbackGuard(self)
     }
 
+    /// Returns a guard that runs `pre` before the rollback action of this guard.
+    ///
+    /// If `pre` fails, its error is the result of the rollback and the original action is not
+    /// run. Otherwise the result of the original action is the result of the rollback.
+    ///
+    /// ```rust
+    /// # use std::cell::RefCell;
+    /// use transaction_rollback::infallible_rollback;
+    ///
+    /// let calls = RefCell::new(Vec::new());
+    /// let guard = infallible_rollback(|| calls.borrow_mut().push("rollback"))
+    ///     .before_rollback(|| Ok(calls.borrow_mut().push("pre")));
+    /// drop(guard);
+    /// assert_eq!(vec!["pre", "rollback"], *calls.borrow());
+    /// ```
+    pub fn before_rollback<F>(self, pre: F) -> RollbackGuard<'a, T, E>
+    where
+        F: FnOnce() -> Result<(), E> + 'a,
+    {
+        self.map_action(move |action| {
+            move |trigger| {
+                pre()?;
+                action(trigger)
+            }
+        })
+    }
+
+    /// Returns a guard that discards the `Ok` value of the rollback action of this guard.
+    ///
+    /// This is useful to collect guards with different `Ok` types, for example into a
+    /// [`crate::RollbackStack`].
+    ///
+    /// ```rust
+    /// use transaction_rollback::{infallible_rollback, RollbackStack};
+    ///
+    /// let mut stack = RollbackStack::default();
+    /// stack.push(infallible_rollback(|| 42).ignore_ok());
+    /// stack.push(infallible_rollback(|| "foo").ignore_ok());
+    /// ```
+    pub fn ignore_ok(self) -> RollbackGuard<'a, (), E>
+    where
+        RollbackGuard<'a, (), E>: private::DropLike,
+    {
+        self.map_action(|action| move |trigger| action(trigger).map(|_| ()))
+    }
+
+    /// Performs the rollback, like [`Rollback::do_rollback`], and returns its result as a
+    /// [`TransactionState`].
+    ///
+    /// The state is always [`TransactionState::Rollback`]. Its operation error is `()`, since
+    /// a guard has no operation, and its rollback result is the result of the rollback action.
+    /// All other slots are `()` as well. This allows reporting rollbacks of guards the same way
+    /// as those of transactions.
+    ///
+    /// ```rust
+    /// use transaction_rollback::{infallible_rollback, TransactionState};
+    ///
+    /// let guard = infallible_rollback(|| 42);
+    /// assert_eq!(TransactionState::Rollback((), Ok(42)), guard.do_rollback_as_state());
+    /// ```
+    pub fn do_rollback_as_state(self) -> TransactionState<(), (), (), T, E, ()> {
+        TransactionState::Rollback((), self.do_rollback())
+    }
+
+    /// Returns a future that performs the rollback, like [`Rollback::do_rollback`], when it is
+    /// first polled.
+    ///
+    /// This allows awaiting the rollback of a guard in async code. The rollback action is
+    /// synchronous and runs inline on the polling task, blocking its executor thread until it
+    /// is done. Slow actions should offload their blocking work themselves, for example via
+    /// `spawn_blocking` of the async runtime, since the guard itself is not [`Send`]. If the
+    /// future is dropped without being polled, the guard is dropped and rolls back as usual.
+    ///
+    /// ```rust
+    /// use std::future::Future;
+    /// use std::pin::pin;
+    /// use std::task::{Context, Poll, Waker};
+    /// use transaction_rollback::infallible_rollback;
+    ///
+    /// let future = pin!(infallible_rollback(|| 42).into_future());
+    /// assert_eq!(
+    ///     Poll::Ready(Ok(42)),
+    ///     future.poll(&mut Context::from_waker(Waker::noop()))
+    /// );
+    /// ```
+    pub fn into_future(self) -> impl Future<Output = Result<T, E>> + 'a {
+        let mut guard = Some(self);
+        poll_fn(move |_| match guard.take() {
+            Some(guard) => Poll::Ready(guard.do_rollback()),
+            None => {
+                panic!("transaction-rollback: the rollback future was polled after completion.")
+            }
+        })
+    }
+
+    /// Starts a chain of guards with this guard and `next`, see [`LinkedGuards`].
+    ///
+    /// When the chain is rolled back, `next` is rolled back before this guard.
+    pub fn link(self, next: RollbackGuard<'a, T, E>) -> LinkedGuards<'a, T, E> {
+        LinkedGuards::new(self).link(next)
+    }
+
+    /// Returns a guard that runs the rollback action of this guard inside a [`tracing`] span.
+    ///
+    /// The span is named `rollback` and created at level `INFO` when the action runs, either
+    /// on drop or via [`Rollback::do_rollback`]. It has the following fields:
+    ///
+    /// - `label`: The given `label`, identifying the guard.
+    /// - `success`: Whether the rollback action succeeded, recorded once it finished.
+    ///
+    /// Only available with the `tracing` feature.
+    ///
+    /// [`tracing`]: https://docs.rs/tracing
+    #[cfg(feature = "tracing")]
+    pub fn traced(self, label: &'static str) -> Self {
+        self.map_action(|action| {
+            move |trigger| {
+                let span = tracing::info_span!("rollback", label, success = tracing::field::Empty);
+                let _enter = span.enter();
+                let result = action(trigger);
+                span.record("success", result.is_ok());
+                result
+            }
+        })
+    }
+
+    /// Bundles the guard with a value, for example context that is needed when deciding whether
+    /// to roll back. See [`ZippedGuard`].
+    pub fn zip<V>(self, value: V) -> ZippedGuard<'a, T, E, V> {
+        ZippedGuard { guard: self, value }
+    }
+
+    /// Splits the rollback into a part that runs now and the remaining part, which stays
+    /// guarded.
+    ///
+    /// The rollback action of this guard is moved into the returned guard as the remaining part,
+    /// and `immediate` is run right away. This is useful if part of the cleanup can already be
+    /// done early, for example to reduce resource pressure, while the rest must wait. The
+    /// returned guard is armed if this guard was armed, and its action runs at most once, like
+    /// any rollback action. If `immediate` panics, the returned guard is dropped while unwinding
+    /// and thus runs the remaining part.
+    ///
+    /// If `immediate` fails, its error is returned together with the returned guard, which is
+    /// still armed. Dropping it then runs the remaining part as usual, and
+    /// [`Rollback::do_rollback`] runs it explicitly. The failed part is not retried.
+    ///
+    /// ```rust
+    /// # use std::cell::RefCell;
+    /// use transaction_rollback::{infallible_rollback, rollback, Rollback, RollbackError};
+    /// # #[derive(Debug, thiserror::Error)]
+    /// # #[error("{0}")]
+    /// # struct ExampleError(&'static str);
+    ///
+    /// let log = RefCell::new(Vec::new());
+    /// let guard = infallible_rollback(|| log.borrow_mut().push("delete file"))
+    ///     .split_action(|| Ok(log.borrow_mut().push("close file")))
+    ///     .unwrap_or_else(|(_err, guard)| guard);
+    /// assert_eq!(vec!["close file"], *log.borrow());
+    /// drop(guard);
+    /// assert_eq!(vec!["close file", "delete file"], *log.borrow());
+    ///
+    /// let Err((err, guard)) = rollback(|| Ok::<_, RollbackError<ExampleError>>("deleted"))
+    ///     .split_action(|| Err(RollbackError(ExampleError("can not close file"))))
+    /// else {
+    ///     unreachable!()
+    /// };
+    /// assert_eq!("can not close file", err.inner().0);
+    /// assert_eq!("deleted", guard.do_rollback().unwrap());
+    /// ```
+    pub fn split_action<F>(self, immediate: F) -> Result<Self, (E, Self)>
+    where
+        F: FnOnce() -> Result<(), E>,
+    {
+        let armed = self.armed;
+        // Guarded again before `immediate` runs, so that the remaining part also runs on panic.
+        let remaining = RollbackGuard {
+            rollback_action: MaybeUninit::new(self.into_action()),
+            armed,
+            mandatory: false,
+            _error_type: PhantomData,
+        };
+        match immediate() {
+            Ok(()) => Ok(remaining),
+            Err(err) => Err((err, remaining)),
+        }
+    }
+
+    /// Returns a guard that runs `fallback` if the rollback action of this guard fails.
+    ///
+    /// `fallback` is called with the error of the rollback action, and its result is the
+    /// result of the returned guard. If the rollback action succeeds, `fallback` is not called.
+    /// This means a failure of `fallback` is reported by [`Rollback::do_rollback`] or, when
+    /// dropped, the drop strategy, while the original error is only passed to `fallback`.
+    ///
+    /// ```rust
+    /// use transaction_rollback::{rollback, Rollback, RollbackError};
+    /// # #[derive(Debug, thiserror::Error)]
+    /// # #[error("{0}")]
+    /// # struct ExampleError(&'static str);
+    ///
+    /// let guard = rollback(|| Err(RollbackError(ExampleError("can not delete file"))))
+    ///     .or_else(|_err| Ok("truncated file instead"));
+    /// assert_eq!("truncated file instead", guard.do_rollback().unwrap());
+    /// ```
+    pub fn or_else<F>(self, fallback: F) -> Self
+    where
+        F: (FnOnce(E) -> Result<T, E>) + 'a,
+    {
+        self.map_action(|action| move |trigger| action(trigger).or_else(fallback))
+    }
+
+    /// Returns a guard that passes the result of the rollback action of this guard to `f`.
+    ///
+    /// When the returned guard is rolled back, the rollback action of this guard runs first and
+    /// `f` runs right after it with its result. The result of `f` is the result of the returned
+    /// guard. `f` is called whether the action succeeded or failed, so nothing is short-circuited:
+    /// `f` decides whether to run a follow-up, for example only if the action reported
+    /// something, and whether to pass an error on or to recover from it.
+    ///
+    /// ```rust
+    /// # use std::cell::RefCell;
+    /// use transaction_rollback::{infallible_rollback, Rollback};
+    ///
+    /// let log = RefCell::new(Vec::new());
+    /// let guard = infallible_rollback(|| "stale lock found").and_then(|result| {
+    ///     if result == Ok("stale lock found") {
+    ///         log.borrow_mut().push("remove lock");
+    ///     }
+    ///     result.map(str::len)
+    /// });
+    /// assert_eq!(Ok(16), guard.do_rollback());
+    /// assert_eq!(vec!["remove lock"], *log.borrow());
+    /// ```
+    pub fn and_then<U, F>(self, f: F) -> RollbackGuard<'a, U, E>
+    where
+        F: (FnOnce(Result<T, E>) -> Result<U, E>) + 'a,
+        RollbackGuard<'a, U, E>: private::DropLike,
+    {
+        self.map_action(|action| move |trigger| f(action(trigger)))
+    }
+
+    /// Registers `callback` to be called with the result of the rollback action.
+    ///
+    /// Unlike the adapters taking `self`, this changes the guard in place, so the result can be
+    /// handled by code that only has a reference to the guard. The callback runs right after
+    /// the action, both when the guard is dropped and when it is rolled back via
+    /// [`Rollback::do_rollback`]. If several callbacks are registered, they run in registration
+    /// order. If the action does not run, for example after [`Self::ok`], no callback runs.
+    ///
+    /// ```rust
+    /// # use std::cell::RefCell;
+    /// use transaction_rollback::infallible_rollback;
+    ///
+    /// let results = RefCell::new(Vec::new());
+    /// let mut guard = infallible_rollback(|| 42);
+    /// guard.on_result(|result| results.borrow_mut().push(format!("first: {result:?}")));
+    /// guard.on_result(|result| results.borrow_mut().push(format!("second: {result:?}")));
+    /// drop(guard);
+    /// assert_eq!(vec!["first: Ok(42)", "second: Ok(42)"], *results.borrow());
+    /// ```
+    pub fn on_result<F>(&mut self, callback: F)
+    where
+        F: FnOnce(&Result<T, E>) + 'a,
+    {
+        // SAFETY: The action is init., since it is only taken when the guard is consumed or
+        // dropped, and it is written back right away.
+        let action = unsafe { self.rollback_action.assume_init_read() };
+        self.rollback_action.write(Box::new(move |trigger| {
+            let result = action(trigger);
+            callback(&result);
+            result
+        }));
+    }
+
+    /// Runs the rollback like dropping the guard does, but returns its result instead of
+    /// handling a failure. This is for collections of guards that handle failures themselves.
+    pub(crate) fn rollback_as_dropped(self) -> Result<T, E> {
+        let mut slf = ManuallyDrop::new(self);
+        // SAFETY: Since we do not drop `Self` (because of the `ManuallyDrop`) its `Drop` code
+        // will not run, and thus the call below will be the only call to `_do_rollback`.
+        unsafe { slf._do_rollback(RollbackTrigger::Drop) }
+    }
+
+    /// Returns a guard with the rollback action built by `f` from the action of this guard. The
+    /// returned guard is armed if this guard is armed.
+    fn map_action<U, F, G>(
+        self,
+        f: impl FnOnce(Box<dyn FnOnce(RollbackTrigger) -> Result<T, E> + 'a>) -> G,
+    ) -> RollbackGuard<'a, U, F>
+    where
+        G: FnOnce(RollbackTrigger) -> Result<U, F> + 'a,
+        F: MaybeError,
+        RollbackGuard<'a, U, F>: private::DropLike,
+    {
+        let armed = self.armed;
+        let mut guard = rollback_with_trigger(f(self.into_action()));
+        guard.armed = armed;
+        guard
+    }
+
+    /// Dismisses the guard and returns its rollback action.
+    fn into_action(self) -> Box<dyn FnOnce(RollbackTrigger) -> Result<T, E> + 'a> {
+        let slf = ManuallyDrop::new(self);
+        // SAFETY: Since we do not drop `Self` (because of the `ManuallyDrop`) its `Drop` code
+        // will not run, so this is the only place the action is read from.
+        unsafe { slf.rollback_action.assume_init_read() }
+    }
+
     /// Does the rollback.
     ///
     /// # Safety
     /// The caller must ensure this is called at most once during the lifetime of the guard.
-    unsafe fn _do_rollback(&mut self) -> Result<T, E> {
+    unsafe fn _do_rollback(&mut self, trigger: RollbackTrigger) -> Result<T, E> {
         // We use mem::replace because `rollback_action` is an `FnOnce` and we can only call it once.
         // SAFETY: The caller guarantees `_do_rollback` is not called again.
         // CLIPPY: This is OK because we never interact with `rollback_action` ever again;
@@ -153,7 +866,247 @@ where
         let action = mem::replace(&mut self.rollback_action, mem::zeroed());
         // SAFETY: `Self::rollback_action` is guaranteed to be init. the first time this function
         // is called and the caller guarantees `_do_rollback` is not called again; see above.
-        (action.assume_init())()
+        (action.assume_init())(trigger)
+    }
+}
+
+impl<'a, T, E> RollbackGuard<'a, RollbackGuard<'a, T, E>, E>
+where
+    E: MaybeError,
+    Self: private::DropLike,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    /// Flattens a guard whose rollback action returns another guard.
+    ///
+    /// When the returned guard is rolled back, the rollback action of this guard is run first.
+    /// If it succeeds, the guard it returned is rolled back immediately, and its result is
+    /// the result of the returned guard. If it fails, its error is the result and there is no
+    /// inner guard to roll back. Both actions run at most once, just like any rollback action.
+    ///
+    /// ```rust
+    /// # use std::cell::RefCell;
+    /// use transaction_rollback::{infallible_rollback, Rollback};
+    ///
+    /// let log = RefCell::new(Vec::new());
+    /// let guard = infallible_rollback(|| {
+    ///     log.borrow_mut().push("outer");
+    ///     infallible_rollback(|| {
+    ///         log.borrow_mut().push("inner");
+    ///         42
+    ///     })
+    /// })
+    /// .flatten();
+    /// assert_eq!(Ok(42), guard.do_rollback());
+    /// assert_eq!(vec!["outer", "inner"], *log.borrow());
+    /// ```
+    pub fn flatten(self) -> RollbackGuard<'a, T, E> {
+        self.map_action(|action| move |trigger| action(trigger)?.into_action()(trigger))
+    }
+}
+
+impl<'a, T, E> RollbackGuard<'a, T, RollbackError<E>>
+where
+    E: Error + Send + Sync + 'static,
+    Self: private::DropLike,
+{
+    /// Returns a guard that catches a panic of the rollback action of this guard and turns it
+    /// into an error.
+    ///
+    /// A panicking rollback action is especially harmful when the guard is dropped while
+    /// unwinding from another panic, since the second panic aborts the process. With this, the
+    /// panic is converted into a [`PanicError`] and then into the error of the guard. When the
+    /// guard is dropped, this error is handled by the [`TryDropStrategy`] like any other
+    /// failure; [`Rollback::do_rollback`] returns it. This is only available for fallible
+    /// guards, since an infallible guard has no error to report the panic with.
+    ///
+    /// The action is run with [`std::panic::AssertUnwindSafe`], so it does not need to be
+    /// [`std::panic::UnwindSafe`]. Since the guard is consumed, only values captured by the
+    /// action can be observed after a panic. They may have been left in an inconsistent state
+    /// by the panic, so they should not be used afterwards without care. Note that the panic
+    /// hook still runs, so the panic is still printed by default.
+    ///
+    /// ```rust
+    /// use transaction_rollback::{rollback, PanicError, Rollback, RollbackError};
+    ///
+    /// #[derive(Debug, thiserror::Error)]
+    /// enum ExampleError {
+    ///     #[error("the rollback panicked")]
+    ///     Panicked,
+    /// }
+    ///
+    /// impl From<PanicError> for ExampleError {
+    ///     fn from(_: PanicError) -> Self {
+    ///         ExampleError::Panicked
+    ///     }
+    /// }
+    ///
+    /// let guard = rollback(|| -> Result<(), RollbackError<ExampleError>> { panic!("oh no") })
+    ///     .catch_panics();
+    /// assert!(matches!(guard.do_rollback(), Err(RollbackError(ExampleError::Panicked))));
+    /// ```
+    pub fn catch_panics(self) -> Self
+    where
+        E: From<PanicError>,
+    {
+        self.map_action(|action| {
+            move |trigger| {
+                catch_unwind(AssertUnwindSafe(|| action(trigger)))
+                    .unwrap_or_else(|panic| Err(RollbackError(E::from(PanicError(panic)))))
+            }
+        })
+    }
+
+    /// Returns a guard that panics if its rollback fails when it is dropped.
+    ///
+    /// The panic message contains the [`Display`] representation of the error. The failure is
+    /// not passed to the [`TryDropStrategy`], so this is a per-guard alternative to installing a
+    /// panicking strategy globally, for rollbacks whose failure can not be tolerated.
+    /// [`Rollback::do_rollback`] is not affected and still returns the error. Making the
+    /// returned guard [`Self::mandatory`] keeps this behaviour: The rollback then always runs on
+    /// drop and panics if it fails.
+    ///
+    /// This also applies if the guard is in a [`crate::RollbackStack`] that is dropped: Even if
+    /// the stack handles failures itself, via [`crate::RollbackStack::on_failures`] or
+    /// [`crate::StackFailureMode::StopOnFirstError`], it rolls back its guards with
+    /// [`RollbackTrigger::Drop`], so the failing guard panics instead of reporting its failure to
+    /// the stack.
+    ///
+    /// If the guard is dropped while unwinding from another panic, for example because it was
+    /// not dismissed before a panic, a failing rollback panics a second time, which aborts the
+    /// process.
+    ///
+    /// ```should_panic
+    /// use transaction_rollback::{rollback, RollbackError};
+    /// # #[derive(Debug, thiserror::Error)]
+    /// # #[error("{0}")]
+    /// # struct ExampleError(&'static str);
+    ///
+    /// let guard = rollback(|| Err::<(), _>(RollbackError(ExampleError("can not delete file"))))
+    ///     .panic_on_rollback_failure();
+    /// drop(guard); // Panics with "... Rollback error: can not delete file".
+    /// ```
+    pub fn panic_on_rollback_failure(self) -> Self {
+        self.map_action(|action| {
+            move |trigger| match (trigger, action(trigger)) {
+                (RollbackTrigger::Drop, Err(err)) => {
+                    panic!("transaction-rollback: a rollback failed on drop: {err}")
+                }
+                (_, result) => result,
+            }
+        })
+    }
+
+    /// Returns an infallible guard that runs the rollback action of this guard and passes its
+    /// error, if any, to `handler`.
+    ///
+    /// `handler` runs at rollback time, that is when the returned guard is dropped or rolled
+    /// back, and only if the rollback action fails. The `Ok` value of the action is discarded.
+    /// This is useful to put a fallible guard into an infallible [`crate::RollbackStack`],
+    /// while still handling its failures.
+    ///
+    /// ```rust
+    /// # use std::cell::RefCell;
+    /// use transaction_rollback::{rollback, RollbackError, RollbackStack};
+    /// # #[derive(Debug, thiserror::Error)]
+    /// # #[error("{0}")]
+    /// # struct ExampleError(&'static str);
+    ///
+    /// let failures = RefCell::new(Vec::new());
+    ///
+    /// let mut stack = RollbackStack::default();
+    /// stack.push(
+    ///     rollback(|| Err::<(), _>(RollbackError(ExampleError("can not delete file"))))
+    ///         .infallible_with(|err| failures.borrow_mut().push(err.to_string())),
+    /// );
+    /// drop(stack);
+    /// assert_eq!(vec!["Rollback error: can not delete file"], *failures.borrow());
+    /// ```
+    pub fn infallible_with<F>(self, handler: F) -> RollbackGuard<'a, (), ()>
+    where
+        F: FnOnce(RollbackError<E>) + 'a,
+    {
+        self.map_action::<(), (), _>(|action| {
+            move |trigger| {
+                if let Err(err) = action(trigger) {
+                    handler(err)
+                }
+                Ok(())
+            }
+        })
+    }
+}
+
+impl RollbackGuard<'static, (), ()> {
+    /// A guard whose rollback does nothing.
+    ///
+    /// Rolling it back via [`Rollback::do_rollback`] returns `Ok(())`, dropping it does
+    /// nothing. This is useful as a default or placeholder, for example in generic code.
+    ///
+    /// ```rust
+    /// use transaction_rollback::{Rollback, RollbackGuard};
+    ///
+    /// assert_eq!(Ok(()), RollbackGuard::noop().do_rollback());
+    /// ```
+    pub fn noop() -> Self {
+        infallible_rollback(|| ())
+    }
+}
+
+impl<'a, T> RollbackGuard<'a, T, ()>
+where
+    Self: private::DropLike,
+{
+    /// Combines this infallible guard with the fallible guard `other` into one fallible guard.
+    ///
+    /// This allows handling guards of mixed fallibility uniformly, for example in one
+    /// [`crate::RollbackStack`]. When the returned guard is rolled back, the rollback action of
+    /// `other` runs first and then the one of this guard, the reverse order of combination. The
+    /// action of this guard always runs, even if the one of `other` fails. The result is `Ok`
+    /// with both results if `other` succeeded, otherwise the error of `other`.
+    ///
+    /// The returned guard is armed if either guard is armed. Since it is fallible, dropping it
+    /// hands a failure of `other` to the configured [`TryDropStrategy`], like for any fallible
+    /// guard.
+    ///
+    /// # Panics
+    /// On rollback, if the action of this guard returns an error, which an infallible
+    /// rollback must not do.
+    ///
+    /// ```rust
+    /// # use std::cell::RefCell;
+    /// use transaction_rollback::{infallible_rollback, rollback, Rollback, RollbackError};
+    /// # #[derive(Debug, thiserror::Error)]
+    /// # #[error("{0}")]
+    /// # struct ExampleError(&'static str);
+    ///
+    /// let log = RefCell::new(Vec::new());
+    /// let guard = infallible_rollback(|| log.borrow_mut().push("unlock"))
+    ///     .and_fallible(rollback(|| {
+    ///         log.borrow_mut().push("delete file");
+    ///         Err::<(), _>(RollbackError(ExampleError("can not delete file")))
+    ///     }));
+    /// assert!(guard.do_rollback().is_err());
+    /// assert_eq!(vec!["delete file", "unlock"], *log.borrow());
+    /// ```
+    pub fn and_fallible<U, E>(
+        self,
+        other: RollbackGuard<'a, U, RollbackError<E>>,
+    ) -> RollbackGuard<'a, (T, U), RollbackError<E>>
+    where
+        E: Error + Send + Sync + 'static,
+    {
+        let armed = self.armed || other.armed;
+        let action = self.into_action();
+        let other = other.into_action();
+        let mut guard = rollback_with_trigger::<_, (T, U), RollbackError<E>>(move |trigger| {
+            let other_result = other(trigger);
+            let result = action(trigger).unwrap_or_else(|()| {
+                panic!("transaction-rollback: an infallible rollback returned an error.")
+            });
+            other_result.map(|u| (result, u))
+        });
+        guard.armed = armed;
+        guard
     }
 }
 
@@ -165,7 +1118,7 @@ where
 
     unsafe fn try_drop(&mut self) -> Result<(), Self::Error> {
         // SAFETY: we called this function inside a `TryDrop::try_drop` context.
-        unsafe { self._do_rollback() }.map(|_| ())
+        unsafe { self._do_rollback(RollbackTrigger::Drop) }.map(|_| ())
     }
 }
 
@@ -195,10 +1148,24 @@ where
 impl<'a, T> private::DropLike for RollbackGuard<'a, T, ()> {
     unsafe fn drop(&mut self) {
         // SAFETY: we called this function inside a `Drop::drop` context.
-        unsafe { self._do_rollback() }.ok();
+        unsafe { self._do_rollback(RollbackTrigger::Drop) }.ok();
     }
 }
 
+/// Handles `error` exactly like the failure of a dropped `RollbackGuard<'a, T, E>`, for
+/// rollbacks that run on drop without such a guard.
+pub(crate) fn handle_drop_failure<'a, T, E>(error: E)
+where
+    T: 'a,
+    E: MaybeError + 'a,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    // Only the drop code of the guard runs, so it is not reported as implicitly rolled back.
+    let mut guard = ManuallyDrop::new(rollback_with_trigger::<_, T, E>(move |_| Err(error)));
+    // SAFETY: The guard is never dropped, so this is the only call.
+    unsafe { private::DropLike::drop(&mut *guard) }
+}
+
 // The actual `Drop` implementation, which just uses the private `DropLike` trait to do the drop.
 // This is implemented this way, because `Drop` can not be specialized.
 impl<'a, T, E> Drop for RollbackGuard<'a, T, E>
@@ -206,11 +1173,44 @@ where
     Self: private::DropLike,
 {
     fn drop(&mut self) {
+        if !self.armed {
+            // SAFETY: The action is still init., since every other place taking it consumes the
+            // guard without dropping it, and `drop` is only called once.
+            unsafe { self.rollback_action.assume_init_drop() };
+            return;
+        }
+        // During unwinding rolling back is the expected outcome.
+        #[cfg(feature = "log")]
+        if !self.mandatory
+            && WARN_ON_IMPLICIT_ROLLBACK.load(Ordering::Relaxed)
+            && !std::thread::panicking()
+        {
+            log::warn!(
+                "{} was dropped without a decision and rolled back implicitly",
+                std::any::type_name::<Self>()
+            );
+        }
         // SAFETY: we called this function inside a `Drop::drop` context.
         unsafe { private::DropLike::drop(self) }
     }
 }
 
+static WARN_ON_IMPLICIT_ROLLBACK: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables warnings for guards that are rolled back implicitly.
+///
+/// If enabled, a warning is emitted whenever a [`RollbackGuard`] is dropped (outside of a
+/// panic) without having been dismissed via [`RollbackGuard::ok`], disarmed via
+/// [`RollbackGuard::disarm`], made mandatory via [`RollbackGuard::mandatory`] or rolled back via
+/// [`Rollback::do_rollback`]. This is often
+/// intended, but can also point to a forgotten call to `ok`.
+///
+/// The warning is logged via the `log` crate, so this only has an effect if the `log` feature
+/// is enabled. This is disabled by default.
+pub fn set_warn_on_implicit_rollback(enabled: bool) {
+    WARN_ON_IMPLICIT_ROLLBACK.store(enabled, Ordering::Relaxed);
+}
+
 /// A rollback that is guaranteed to run on [`Drop`].
 ///
 /// To create use [`RollbackGuard::mandatory`].
@@ -232,12 +1232,8 @@ where
     }
 }
 
-/// The sealed pattern prevents other traits from implementing any trait that is `Sealed`.
-mod private {
-    use super::RollbackError;
-    use std::error::Error;
-
-    pub trait Sealed {}
+/// Keeps `DropLike` out of the public API, so it can not be implemented elsewhere.
+pub(crate) mod private {
     /// This is basically [`Drop`]
     /// We need this trait since [`Drop`] can not be specialized.
     pub trait DropLike {
@@ -245,7 +1241,4 @@ mod private {
         /// The caller must guarantee this does not get called more than once.
         unsafe fn drop(&mut self) {}
     }
-
-    impl Sealed for () {}
-    impl<E: Error + Send + Sync + 'static> Sealed for RollbackError<E> {}
 }
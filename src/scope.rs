@@ -0,0 +1,100 @@
+use crate::{infallible_rollback, RollbackGuard};
+use std::cell::RefCell;
+
+thread_local! {
+    static SCOPES: RefCell<Vec<Vec<RollbackGuard<'static, (), ()>>>> = const {
+        RefCell::new(Vec::new())
+    };
+}
+
+/// Runs `f` in a rollback scope, to which rollbacks can be added via [`scoped_rollback`].
+///
+/// If `f` returns `Err` or panics, all rollbacks added to the scope are run in reverse order
+/// (LIFO). If it returns `Ok`, they are dismissed. This avoids having to pass guards through
+/// every function between the place where a rollback is registered and the place where it is
+/// decided whether the whole operation succeeded.
+///
+/// Scopes are thread-local: [`scoped_rollback`] adds to the innermost scope on the current
+/// thread. Scopes can be nested. If a nested scope succeeds, its rollbacks are moved to the
+/// enclosing scope instead of being dismissed, so they still run if the enclosing scope fails.
+///
+/// ```rust
+/// # use std::cell::RefCell;
+/// # use std::rc::Rc;
+/// use transaction_rollback::{scoped_rollback, with_rollback_scope};
+///
+/// let log = Rc::new(RefCell::new(Vec::new()));
+///
+/// fn create(log: &Rc<RefCell<Vec<String>>>, name: &str) {
+///     let (log, name) = (log.clone(), name.to_string());
+///     scoped_rollback(move || log.borrow_mut().push(format!("delete {name}")));
+/// }
+///
+/// let result: Result<(), &str> = with_rollback_scope(|| {
+///     create(&log, "a");
+///     create(&log, "b");
+///     Err("oh no")
+/// });
+/// assert!(result.is_err());
+/// assert_eq!(vec!["delete b", "delete a"], *log.borrow());
+/// ```
+pub fn with_rollback_scope<T, E, F>(f: F) -> Result<T, E>
+where
+    F: FnOnce() -> Result<T, E>,
+{
+    SCOPES.with(|s| s.borrow_mut().push(Vec::new()));
+    let mut scope = ScopeGuard { ok: false };
+    let result = f();
+    scope.ok = result.is_ok();
+    result
+}
+
+/// Adds a rollback to the innermost scope of [`with_rollback_scope`] on this thread.
+///
+/// # Panics
+/// If there is no such scope.
+pub fn scoped_rollback<F>(rollback_action: F)
+where
+    F: FnOnce() + 'static,
+{
+    let guard = infallible_rollback(rollback_action);
+    SCOPES.with(|s| match s.borrow_mut().last_mut() {
+        Some(scope) => scope.push(guard),
+        None => {
+            guard.ok();
+            panic!(
+                "transaction-rollback: `scoped_rollback` was called outside of \
+                 `with_rollback_scope`."
+            )
+        }
+    })
+}
+
+/// Ends the innermost scope when dropped, also if the scope panicked.
+struct ScopeGuard {
+    ok: bool,
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        // The rollbacks must run after the borrow ended, since they may add rollbacks themselves.
+        let guards = SCOPES.with(|s| {
+            let mut scopes = s.borrow_mut();
+            let guards = scopes.pop().unwrap_or_default();
+            match scopes.last_mut() {
+                Some(parent) if self.ok => {
+                    parent.extend(guards);
+                    Vec::new()
+                }
+                _ => guards,
+            }
+        });
+        for guard in guards.into_iter().rev() {
+            if self.ok {
+                guard.ok();
+            } else {
+                drop(guard);
+            }
+        }
+    }
+}
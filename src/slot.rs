@@ -0,0 +1,104 @@
+use crate::{infallible_rollback, Rollback, RollbackGuard};
+use std::cell::Cell;
+use std::sync::Mutex;
+
+/// A rollback that can not fail, which stores the value produced by the rollback into `slot`
+/// when the returned guard is dropped.
+///
+/// If the rollback is instead run manually via [`Rollback::do_rollback`], the value is returned
+/// as usual and `slot` is left untouched.
+///
+/// `slot` can be a [`Cell<Option<T>>`], which can only be used on a single thread, or a
+/// [`Mutex<Option<T>>`] if the value needs to be read from another thread.
+///
+/// ```rust
+/// # use std::cell::Cell;
+/// use transaction_rollback::infallible_rollback_into;
+///
+/// let slot = Cell::new(None);
+/// {
+///     let _guard = infallible_rollback_into(|| "rolled back", &slot);
+/// }
+/// assert_eq!(Some("rolled back"), slot.take());
+/// ```
+pub fn infallible_rollback_into<'a, F, T, S>(
+    rollback_action: F,
+    slot: &'a S,
+) -> SlotRollbackGuard<'a, T, S>
+where
+    F: (FnOnce() -> T) + 'a,
+    S: RollbackSlot<T> + ?Sized,
+{
+    SlotRollbackGuard {
+        guard: Some(infallible_rollback(rollback_action)),
+        slot,
+    }
+}
+
+/// A place the value of a rollback can be stored into, see [`infallible_rollback_into`].
+pub trait RollbackSlot<T> {
+    /// Stores `value` into the slot, replacing any previous value.
+    fn put(&self, value: T);
+}
+
+impl<T> RollbackSlot<T> for Cell<Option<T>> {
+    fn put(&self, value: T) {
+        self.set(Some(value));
+    }
+}
+
+impl<T> RollbackSlot<T> for Mutex<Option<T>> {
+    /// If the mutex is poisoned, the value is still stored.
+    fn put(&self, value: T) {
+        *self.lock().unwrap_or_else(|e| e.into_inner()) = Some(value);
+    }
+}
+
+/// A rollback that can not fail and stores its value into a [`RollbackSlot`] when dropped.
+///
+/// To create this see [`infallible_rollback_into`].
+pub struct SlotRollbackGuard<'a, T, S>
+where
+    S: RollbackSlot<T> + ?Sized,
+{
+    guard: Option<RollbackGuard<'a, T, ()>>,
+    slot: &'a S,
+}
+
+impl<'a, T, S> SlotRollbackGuard<'a, T, S>
+where
+    S: RollbackSlot<T> + ?Sized,
+{
+    /// Drops the rollback guard but does not run the rollback function.
+    /// The slot is left untouched.
+    pub fn ok(mut self) {
+        if let Some(guard) = self.guard.take() {
+            guard.ok()
+        }
+    }
+}
+
+impl<'a, T, S> Rollback for SlotRollbackGuard<'a, T, S>
+where
+    S: RollbackSlot<T> + ?Sized,
+{
+    type RollbackOk = T;
+    type RollbackError = ();
+
+    /// Performs the rollback, consuming the guard. The slot is left untouched.
+    fn do_rollback(mut self) -> Result<T, ()> {
+        // The guard is only ever taken when consuming `self`, so it is always present here.
+        self.guard.take().ok_or(())?.do_rollback()
+    }
+}
+
+impl<'a, T, S> Drop for SlotRollbackGuard<'a, T, S>
+where
+    S: RollbackSlot<T> + ?Sized,
+{
+    fn drop(&mut self) {
+        if let Some(Ok(value)) = self.guard.take().map(Rollback::do_rollback) {
+            self.slot.put(value);
+        }
+    }
+}
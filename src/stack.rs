@@ -0,0 +1,811 @@
+use crate::rollback::{handle_drop_failure, private};
+use crate::{MaybeError, Rollback, RollbackGuard};
+use std::cmp::Reverse;
+use std::fmt::{Debug, Formatter};
+use std::mem;
+use std::time::Instant;
+
+/// The order in which the guards of a [`RollbackStack`] are rolled back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ExecutionOrder {
+    /// The guard pushed last is rolled back first. This matches the usual acquisition/release
+    /// discipline, where later resources may depend on earlier ones.
+    #[default]
+    Lifo,
+    /// The guard pushed first is rolled back first. Useful for sets of independent resources.
+    Fifo,
+}
+
+/// How a [`RollbackStack`] continues after one of its guards failed to roll back, see
+/// [`RollbackStack::with_failure_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum StackFailureMode {
+    /// All guards are rolled back, even if some of them fail, and all failures are collected.
+    #[default]
+    Aggregate,
+    /// No further guard is rolled back after the first failure. The remaining guards are
+    /// dismissed without running them. Useful if a failed step makes later steps unsafe.
+    StopOnFirstError,
+}
+
+/// A collection of [`RollbackGuard`]s that are rolled back together.
+///
+/// When dropped, all contained guards are dropped (and thus rolled back) in the
+/// [`ExecutionOrder`] the stack was created with, unless they were pushed with a priority via
+/// [`RollbackStack::push_with_priority`]. [`Rollback::do_rollback`] runs all guards
+/// in the same order and collects their results.
+///
+/// This makes the order of rollbacks explicit: Unlike guards stored in local variables, which
+/// are dropped in reverse declaration order, the order does not change when code is
+/// rearranged, as long as the guards are pushed in the same order.
+///
+/// ```rust
+/// # use std::cell::RefCell;
+/// use transaction_rollback::{infallible_rollback, ExecutionOrder, RollbackStack};
+///
+/// let calls = RefCell::new(Vec::new());
+///
+/// let mut stack = RollbackStack::new(ExecutionOrder::Lifo);
+/// stack.push(infallible_rollback(|| calls.borrow_mut().push(1)));
+/// stack.push(infallible_rollback(|| calls.borrow_mut().push(2)));
+/// stack.push(infallible_rollback(|| calls.borrow_mut().push(3)));
+/// drop(stack);
+/// assert_eq!(vec![3, 2, 1], *calls.borrow());
+///
+/// calls.borrow_mut().clear();
+///
+/// let mut stack = RollbackStack::new(ExecutionOrder::Fifo);
+/// stack.push(infallible_rollback(|| calls.borrow_mut().push(1)));
+/// stack.push(infallible_rollback(|| calls.borrow_mut().push(2)));
+/// stack.push(infallible_rollback(|| calls.borrow_mut().push(3)));
+/// drop(stack);
+/// assert_eq!(vec![1, 2, 3], *calls.borrow());
+/// ```
+///
+/// The order also applies to [`Rollback::do_rollback`]:
+///
+/// ```rust
+/// use transaction_rollback::{infallible_rollback, ExecutionOrder, Rollback, RollbackStack};
+///
+/// let mut stack = RollbackStack::new(ExecutionOrder::Lifo);
+/// stack.push(infallible_rollback(|| 1));
+/// stack.push(infallible_rollback(|| 2));
+/// assert_eq!(Ok(vec![2, 1]), stack.do_rollback());
+///
+/// let mut stack = RollbackStack::new(ExecutionOrder::Fifo);
+/// stack.push(infallible_rollback(|| 1));
+/// stack.push(infallible_rollback(|| 2));
+/// assert_eq!(Ok(vec![1, 2]), stack.do_rollback());
+/// ```
+pub struct RollbackStack<'a, T, E>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    // Guards with their priority, `None` if they were pushed without one.
+    guards: Vec<(Option<i32>, RollbackGuard<'a, T, E>)>,
+    order: ExecutionOrder,
+    failure_mode: StackFailureMode,
+    on_failures: Option<Box<dyn FnOnce(Vec<E>) + 'a>>,
+    #[allow(clippy::type_complexity)]
+    on_not_run: Option<Box<dyn FnOnce(Vec<RollbackGuard<'a, T, E>>) + 'a>>,
+    deadline: Option<Instant>,
+}
+
+impl<'a, T, E> RollbackStack<'a, T, E>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    /// Creates a new, empty stack that rolls back its guards in the given order.
+    pub fn new(order: ExecutionOrder) -> Self {
+        Self {
+            guards: Vec::new(),
+            order,
+            failure_mode: StackFailureMode::default(),
+            on_failures: None,
+            on_not_run: None,
+            deadline: None,
+        }
+    }
+
+    /// Creates a new, empty stack like [`Self::new`], with space for at least `capacity` guards.
+    ///
+    /// This avoids reallocations if many guards are pushed, see [`Vec::with_capacity`].
+    pub fn with_capacity(order: ExecutionOrder, capacity: usize) -> Self {
+        let mut stack = Self::new(order);
+        stack.reserve(capacity);
+        stack
+    }
+
+    /// Registers a callback that is invoked once with all failures, if any of the rollbacks
+    /// fail when the stack is dropped.
+    ///
+    /// If this is set, the guards are not dropped individually, but the stack runs their
+    /// rollbacks itself, still with [`crate::RollbackTrigger::Drop`]. This means their failures
+    /// are not handled by the [`try_drop`] strategies, but are only passed to `on_failures`, in
+    /// execution order.
+    ///
+    /// The callback is not invoked if the stack is rolled back via [`Rollback::do_rollback`],
+    /// since that returns the failures directly.
+    ///
+    /// ```rust
+    /// # use std::cell::RefCell;
+    /// use transaction_rollback::{rollback, ExecutionOrder, RollbackError, RollbackStack};
+    ///
+    /// #[derive(Debug, thiserror::Error)]
+    /// #[error("{0}")]
+    /// struct ExampleError(&'static str);
+    ///
+    /// let failures = RefCell::new(Vec::new());
+    ///
+    /// let mut stack = RollbackStack::new(ExecutionOrder::Lifo).on_failures(
+    ///     |errs: Vec<RollbackError<ExampleError>>| {
+    ///         failures.borrow_mut().extend(errs.into_iter().map(|e| e.0 .0))
+    ///     },
+    /// );
+    /// stack.push(rollback(|| Err(RollbackError(ExampleError("first")))));
+    /// stack.push(rollback(|| Ok(())));
+    /// stack.push(rollback(|| Err(RollbackError(ExampleError("third")))));
+    /// drop(stack);
+    ///
+    /// assert_eq!(vec!["third", "first"], *failures.borrow());
+    /// ```
+    pub fn on_failures<F>(mut self, on_failures: F) -> Self
+    where
+        F: FnOnce(Vec<E>) + 'a,
+    {
+        self.on_failures = Some(Box::new(on_failures));
+        self
+    }
+
+    /// Sets how the stack continues after a guard failed to roll back.
+    ///
+    /// By default, [`StackFailureMode::Aggregate`], all guards are rolled back even if some of
+    /// them fail. With [`StackFailureMode::StopOnFirstError`], the remaining guards are not run
+    /// after the first failure. How they are surfaced depends on how the stack is rolled back:
+    ///
+    /// - [`Self::run_and_report`] returns a report with the results up to the failure, which
+    ///   also holds the guards that did not run, see [`RollbackReport::take_not_run`].
+    /// - When the stack is dropped, the guards that did not run are passed to the callback
+    ///   registered via [`Self::on_not_run`]. Without a callback they are dismissed (see
+    ///   [`RollbackGuard::ok`]).
+    /// - [`Rollback::do_rollback`] returns only the first error, and dismisses the guards that
+    ///   did not run. Use `run_and_report` to learn about them.
+    ///
+    /// When the stack is dropped in this mode, the stack runs the rollbacks of the guards itself,
+    /// still with [`crate::RollbackTrigger::Drop`], to detect the failure. The failure is passed to
+    /// [`Self::on_failures`] if it is set, otherwise it is handled like the failure of a dropped
+    /// guard.
+    ///
+    /// ```rust
+    /// # use std::cell::RefCell;
+    /// use transaction_rollback::{rollback, RollbackError, RollbackStack, StackFailureMode};
+    /// # #[derive(Debug, thiserror::Error)]
+    /// # #[error("{0}")]
+    /// # struct ExampleError(&'static str);
+    ///
+    /// let calls = &RefCell::new(Vec::new());
+    /// let call = |name, ok| {
+    ///     rollback(move || {
+    ///         calls.borrow_mut().push(name);
+    ///         match ok {
+    ///             true => Ok(name),
+    ///             false => Err(RollbackError(ExampleError("can not unmount"))),
+    ///         }
+    ///     })
+    /// };
+    ///
+    /// let mut stack =
+    ///     RollbackStack::default().with_failure_mode(StackFailureMode::StopOnFirstError);
+    /// stack.push(call("remove directory", true));
+    /// stack.push(call("unmount", false));
+    /// stack.push(call("close files", true));
+    /// let report = stack.run_and_report();
+    ///
+    /// assert_eq!(vec!["close files", "unmount"], *calls.borrow());
+    /// assert_eq!(vec![&"close files"], report.successes().collect::<Vec<_>>());
+    /// assert_eq!(1, report.failures().count());
+    /// assert_eq!(1, report.not_run());
+    /// ```
+    pub fn with_failure_mode(mut self, failure_mode: StackFailureMode) -> Self {
+        self.failure_mode = failure_mode;
+        self
+    }
+
+    /// Registers a callback that is invoked with the guards that were not rolled back when the
+    /// stack was dropped, because it stopped on the first failure (see
+    /// [`StackFailureMode::StopOnFirstError`]) or the deadline set via [`Self::with_deadline`]
+    /// had passed.
+    ///
+    /// The guards are passed in execution order and are still armed, so the callback decides
+    /// what happens to them: Dropping them rolls them back, [`RollbackGuard::ok`] dismisses
+    /// them. The callback is not invoked if all guards ran.
+    ///
+    /// ```rust
+    /// # use std::cell::Cell;
+    /// use transaction_rollback::{rollback, RollbackError, RollbackStack, StackFailureMode};
+    /// # #[derive(Debug, thiserror::Error)]
+    /// # #[error("{0}")]
+    /// # struct ExampleError(&'static str);
+    ///
+    /// let not_run = Cell::new(0);
+    /// let mut stack = RollbackStack::default()
+    ///     .with_failure_mode(StackFailureMode::StopOnFirstError)
+    ///     .on_failures(|_| ())
+    ///     .on_not_run(|guards| {
+    ///         not_run.set(guards.len());
+    ///         guards.into_iter().for_each(|guard| guard.ok());
+    ///     });
+    /// stack.push(rollback(|| Ok(())));
+    /// stack.push(rollback(|| Ok(())));
+    /// stack.push(rollback(|| Err(RollbackError(ExampleError("can not unmount")))));
+    /// drop(stack);
+    ///
+    /// assert_eq!(2, not_run.get());
+    /// ```
+    pub fn on_not_run<F>(mut self, on_not_run: F) -> Self
+    where
+        F: FnOnce(Vec<RollbackGuard<'a, T, E>>) + 'a,
+    {
+        self.on_not_run = Some(Box::new(on_not_run));
+        self
+    }
+
+    /// Sets a deadline for rolling back the guards when the stack is dropped.
+    ///
+    /// Before each guard is rolled back, the deadline is checked. Once it has passed, the
+    /// remaining guards are not run. Like the guards skipped after a failure, they are passed to
+    /// the callback registered via [`Self::on_not_run`], so the caller knows that the cleanup is
+    /// incomplete. Without a callback they are dismissed (see [`RollbackGuard::ok`]). This
+    /// prevents a slow teardown from blocking indefinitely.
+    ///
+    /// A rollback that is already running is not interrupted, so the deadline can still be
+    /// exceeded by a single slow rollback; limiting the time of individual rollbacks is up to
+    /// the rollbacks themselves. The deadline does not apply to [`Rollback::do_rollback`].
+    ///
+    /// ```rust
+    /// # use std::cell::{Cell, RefCell};
+    /// use std::time::{Duration, Instant};
+    /// use transaction_rollback::{infallible_rollback, RollbackStack};
+    ///
+    /// let calls = &RefCell::new(Vec::new());
+    /// let not_run = &Cell::new(0);
+    /// let stack = |deadline| {
+    ///     let mut stack = RollbackStack::default()
+    ///         .with_deadline(deadline)
+    ///         .on_not_run(|guards| {
+    ///             not_run.set(guards.len());
+    ///             guards.into_iter().for_each(|guard| guard.ok());
+    ///         });
+    ///     stack.push(infallible_rollback(|| calls.borrow_mut().push(1)));
+    ///     stack.push(infallible_rollback(|| calls.borrow_mut().push(2)));
+    ///     stack
+    /// };
+    ///
+    /// // The deadline has already passed, so no guard runs.
+    /// drop(stack(Instant::now()));
+    /// assert!(calls.borrow().is_empty());
+    /// assert_eq!(2, not_run.get());
+    ///
+    /// not_run.set(0);
+    /// drop(stack(Instant::now() + Duration::from_secs(3600)));
+    /// assert_eq!(vec![2, 1], *calls.borrow());
+    /// assert_eq!(0, not_run.get());
+    /// ```
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// The order in which the guards of this stack are rolled back.
+    pub fn order(&self) -> ExecutionOrder {
+        self.order
+    }
+
+    /// How the stack continues after a guard failed to roll back, see
+    /// [`Self::with_failure_mode`].
+    pub fn failure_mode(&self) -> StackFailureMode {
+        self.failure_mode
+    }
+
+    /// Adds a guard to the stack.
+    ///
+    /// The guard has a priority of `0`, see [`Self::push_with_priority`]. Guards pushed this
+    /// way are rolled back in the [`ExecutionOrder`] of the stack.
+    pub fn push(&mut self, guard: RollbackGuard<'a, T, E>) {
+        self.guards.push((None, guard));
+    }
+
+    /// Adds a guard to the stack with a priority.
+    ///
+    /// Guards with a higher priority are rolled back before guards with a lower priority,
+    /// regardless of when they were pushed. Guards with the same priority are rolled back in
+    /// reverse registration order, regardless of the [`ExecutionOrder`] of the stack.
+    ///
+    /// Guards pushed via [`Self::push`] have a priority of `0`, but keep the [`ExecutionOrder`]
+    /// among each other. They are rolled back after the guards pushed with a priority of `0`.
+    ///
+    /// ```rust
+    /// # use std::cell::RefCell;
+    /// use transaction_rollback::{infallible_rollback, ExecutionOrder, RollbackStack};
+    ///
+    /// let calls = &RefCell::new(Vec::new());
+    /// let call = |name| infallible_rollback(move || calls.borrow_mut().push(name));
+    ///
+    /// let mut stack = RollbackStack::new(ExecutionOrder::Lifo);
+    /// stack.push(call("file 1"));
+    /// stack.push_with_priority(10, call("connection 1"));
+    /// stack.push(call("file 2"));
+    /// stack.push_with_priority(-1, call("log"));
+    /// stack.push_with_priority(10, call("connection 2"));
+    /// drop(stack);
+    /// assert_eq!(
+    ///     vec!["connection 2", "connection 1", "file 2", "file 1", "log"],
+    ///     *calls.borrow()
+    /// );
+    ///
+    /// calls.borrow_mut().clear();
+    ///
+    /// let mut stack = RollbackStack::new(ExecutionOrder::Fifo);
+    /// stack.push(call("file 1"));
+    /// stack.push_with_priority(10, call("connection 1"));
+    /// stack.push(call("file 2"));
+    /// stack.push_with_priority(0, call("cache"));
+    /// stack.push_with_priority(10, call("connection 2"));
+    /// drop(stack);
+    /// assert_eq!(
+    ///     vec!["connection 2", "connection 1", "cache", "file 1", "file 2"],
+    ///     *calls.borrow()
+    /// );
+    /// ```
+    pub fn push_with_priority(&mut self, priority: i32, guard: RollbackGuard<'a, T, E>) {
+        self.guards.push((Some(priority), guard));
+    }
+
+    /// The number of guards in the stack.
+    pub fn len(&self) -> usize {
+        self.guards.len()
+    }
+
+    /// Returns `true` if the stack contains no guards.
+    pub fn is_empty(&self) -> bool {
+        self.guards.is_empty()
+    }
+
+    /// The number of guards the stack can hold without reallocating, see [`Vec::capacity`].
+    pub fn capacity(&self) -> usize {
+        self.guards.capacity()
+    }
+
+    /// Reserves space for at least `additional` more guards, see [`Vec::reserve`].
+    ///
+    /// ```rust
+    /// use transaction_rollback::{infallible_rollback, ExecutionOrder, RollbackStack};
+    ///
+    /// let mut stack = RollbackStack::with_capacity(ExecutionOrder::Lifo, 10);
+    /// assert!(stack.capacity() >= 10);
+    /// stack.reserve(100);
+    /// assert!(stack.capacity() >= 100);
+    /// for _ in 0..100 {
+    ///     stack.push(infallible_rollback(|| ()));
+    /// }
+    /// # stack.ok();
+    /// ```
+    pub fn reserve(&mut self, additional: usize) {
+        self.guards.reserve(additional);
+    }
+
+    /// Drops the stack but does not run any of the rollbacks, see [`RollbackGuard::ok`].
+    pub fn ok(mut self) {
+        for (_, guard) in mem::take(&mut self.guards) {
+            guard.ok();
+        }
+    }
+
+    /// Returns the guards of the stack without running any of them, in execution order.
+    ///
+    /// The guards stay armed, so each of them rolls back when it is dropped. Since a [`Vec`]
+    /// drops its items from first to last, dropping the returned guards together rolls them
+    /// back in the same order as the stack would have. `on_failures` is not invoked.
+    ///
+    /// ```rust
+    /// # use std::cell::RefCell;
+    /// use transaction_rollback::{infallible_rollback, RollbackStack};
+    ///
+    /// let calls = RefCell::new(Vec::new());
+    ///
+    /// let mut stack = RollbackStack::default();
+    /// stack.push(infallible_rollback(|| calls.borrow_mut().push(1)));
+    /// stack.push(infallible_rollback(|| calls.borrow_mut().push(2)));
+    /// let mut guards = stack.into_guards();
+    /// assert!(calls.borrow().is_empty());
+    ///
+    /// guards.remove(1).ok();
+    /// drop(guards);
+    /// assert_eq!(vec![2], *calls.borrow());
+    /// ```
+    pub fn into_guards(mut self) -> Vec<RollbackGuard<'a, T, E>> {
+        self.take_ordered()
+    }
+
+    /// Rolls back all guards, consuming the stack, and returns the result of each of them.
+    ///
+    /// Like [`Rollback::do_rollback`], all guards are rolled back, even if some of them fail,
+    /// unless the stack stops on the first failure, see [`Self::with_failure_mode`]. Instead of
+    /// separating successes and failures, the [`RollbackReport`] keeps the result of each guard
+    /// that ran in execution order. `on_failures` is not invoked.
+    ///
+    /// ```rust
+    /// use transaction_rollback::{rollback, RollbackError, RollbackStack};
+    ///
+    /// #[derive(Debug, thiserror::Error)]
+    /// #[error("{0}")]
+    /// struct ExampleError(&'static str);
+    ///
+    /// let mut stack = RollbackStack::default();
+    /// stack.push(rollback(|| Ok(1)));
+    /// stack.push(rollback(|| Err(RollbackError(ExampleError("can not delete file")))));
+    /// stack.push(rollback(|| Ok(3)));
+    /// let report = stack.run_and_report();
+    ///
+    /// assert_eq!(vec![&3, &1], report.successes().collect::<Vec<_>>());
+    /// assert_eq!(1, report.failures().count());
+    /// assert_eq!(
+    ///     vec![true, false, true],
+    ///     report.into_iter().map(|r| r.is_ok()).collect::<Vec<_>>()
+    /// );
+    /// ```
+    pub fn run_and_report(mut self) -> RollbackReport<'a, T, E> {
+        let mut guards = self.take_ordered().into_iter();
+        let mut results = Vec::with_capacity(guards.len());
+        for guard in guards.by_ref() {
+            let result = guard.do_rollback();
+            let failed = result.is_err();
+            results.push(result);
+            if failed && self.failure_mode == StackFailureMode::StopOnFirstError {
+                break;
+            }
+        }
+        RollbackReport {
+            results,
+            not_run: guards.collect(),
+        }
+    }
+
+    /// Removes all guards from the stack, in execution order.
+    fn take_ordered(&mut self) -> Vec<RollbackGuard<'a, T, E>> {
+        let (mut guards, mut unprioritized): (Vec<_>, Vec<_>) = mem::take(&mut self.guards)
+            .into_iter()
+            .partition(|(priority, _)| priority.is_some());
+        guards.reverse();
+        if self.order == ExecutionOrder::Lifo {
+            unprioritized.reverse();
+        }
+        guards.append(&mut unprioritized);
+        // The sort is stable, so guards with the same priority keep the order from above.
+        guards.sort_by_key(|(priority, _)| Reverse(priority.unwrap_or(0)));
+        guards.into_iter().map(|(_, guard)| guard).collect()
+    }
+}
+
+impl<'a, T, E> Default for RollbackStack<'a, T, E>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    fn default() -> Self {
+        Self::new(ExecutionOrder::default())
+    }
+}
+
+impl<'a, T, E> Rollback for RollbackStack<'a, T, E>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    type RollbackOk = Vec<T>;
+    type RollbackError = Vec<E>;
+
+    /// Performs the rollback of all guards, consuming the stack.
+    ///
+    /// All guards are rolled back, even if some of them fail, unless the stack stops on the
+    /// first failure, see [`RollbackStack::with_failure_mode`]. If all of them succeed, their
+    /// results are returned in execution order, otherwise all errors are returned in
+    /// execution order.
+    fn do_rollback(self) -> Result<Vec<T>, Vec<E>> {
+        collapse_results(self.run_and_report())
+    }
+}
+
+impl<'a, T, E> Drop for RollbackStack<'a, T, E>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    fn drop(&mut self) {
+        let mut guards = self.take_ordered().into_iter().peekable();
+        let on_failures = self.on_failures.take();
+        let stop_on_first_error = self.failure_mode == StackFailureMode::StopOnFirstError;
+        let deadline = self.deadline;
+        let deadline_passed = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        let mut failures = Vec::new();
+        // The guards that are left in `guards` after the loop did not run.
+        while let Some(guard) = guards.next_if(|_| !deadline_passed()) {
+            if !guard.is_armed() || (on_failures.is_none() && !stop_on_first_error) {
+                // The guard handles a failing rollback on its own when dropped, and does
+                // nothing if it is disarmed.
+                drop(guard);
+            } else if let Err(err) = guard.rollback_as_dropped() {
+                match on_failures {
+                    Some(_) => failures.push(err),
+                    // Handled like the failure of a dropped guard.
+                    None => handle_drop_failure::<T, _>(err),
+                }
+                if stop_on_first_error {
+                    break;
+                }
+            }
+        }
+        match self.on_not_run.take() {
+            Some(on_not_run) if guards.len() > 0 => on_not_run(guards.collect()),
+            _ => guards.for_each(RollbackGuard::ok),
+        }
+
+        if let Some(on_failures) = on_failures {
+            if !failures.is_empty() {
+                on_failures(failures)
+            }
+        }
+    }
+}
+
+/// Collapses results into a single `Result`: `Ok` with all values if all results are `Ok`,
+/// otherwise `Err` with all errors. The order of the values and errors is kept.
+///
+/// This is useful to decide whether a teardown succeeded, for example with the results of
+/// [`RollbackStack::run_and_report`], but works for any results.
+///
+/// ```rust
+/// use transaction_rollback::collapse_results;
+///
+/// assert_eq!(Ok(vec![1, 2]), collapse_results(vec![Ok::<_, &str>(1), Ok(2)]));
+/// assert_eq!(Err(vec!["a", "b"]), collapse_results(vec![Err::<i32, _>("a"), Err("b")]));
+/// assert_eq!(Err(vec!["b"]), collapse_results(vec![Ok(1), Err("b"), Ok(3)]));
+/// assert_eq!(Ok(vec![]), collapse_results(Vec::<Result<i32, &str>>::new()));
+/// ```
+pub fn collapse_results<T, E>(
+    results: impl IntoIterator<Item = Result<T, E>>,
+) -> Result<Vec<T>, Vec<E>> {
+    let mut oks = Vec::new();
+    let mut errs = Vec::new();
+    for result in results {
+        match result {
+            Ok(v) => oks.push(v),
+            Err(e) => errs.push(e),
+        }
+    }
+    if errs.is_empty() {
+        Ok(oks)
+    } else {
+        Err(errs)
+    }
+}
+
+/// A chain of guards built via [`RollbackGuard::link`].
+///
+/// The guards are rolled back in reverse link order: For `a.link(b).link(c)`, `c` is rolled
+/// back first, then `b` and finally `a`. This is the same as pushing the guards in link order
+/// onto a [`RollbackStack`] with [`ExecutionOrder::Lifo`], which is what this wraps. Dropping
+/// the chain rolls back all guards, and [`Rollback::do_rollback`] collects their results as
+/// for a [`RollbackStack`].
+///
+/// ```rust
+/// # use std::cell::RefCell;
+/// use transaction_rollback::{infallible_rollback, Rollback};
+///
+/// let log = &RefCell::new(Vec::new());
+/// let call = |name| infallible_rollback(move || log.borrow_mut().push(name));
+///
+/// let chain = call("a").link(call("b")).link(call("c"));
+/// assert_eq!(3, chain.len());
+/// drop(chain);
+/// assert_eq!(vec!["c", "b", "a"], *log.borrow());
+/// ```
+pub struct LinkedGuards<'a, T, E>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    stack: RollbackStack<'a, T, E>,
+}
+
+impl<'a, T, E> LinkedGuards<'a, T, E>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    pub(crate) fn new(first: RollbackGuard<'a, T, E>) -> Self {
+        let mut stack = RollbackStack::new(ExecutionOrder::Lifo);
+        stack.push(first);
+        Self { stack }
+    }
+
+    /// Adds `next` to the chain. It is rolled back before all guards linked so far.
+    pub fn link(mut self, next: RollbackGuard<'a, T, E>) -> Self {
+        self.stack.push(next);
+        self
+    }
+
+    /// The number of guards in the chain.
+    pub fn len(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Always `false`, since a chain contains at least the guard it was started from.
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    /// Drops the chain but does not run any of the rollbacks, see [`RollbackGuard::ok`].
+    pub fn ok(self) {
+        self.stack.ok()
+    }
+
+    /// Returns the underlying [`RollbackStack`], for example to push further guards.
+    pub fn into_stack(self) -> RollbackStack<'a, T, E> {
+        self.stack
+    }
+}
+
+impl<'a, T, E> Rollback for LinkedGuards<'a, T, E>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    type RollbackOk = Vec<T>;
+    type RollbackError = Vec<E>;
+
+    /// Performs the rollback of all guards in reverse link order, see
+    /// [`RollbackStack::do_rollback`](Rollback::do_rollback).
+    fn do_rollback(self) -> Result<Vec<T>, Vec<E>> {
+        self.stack.do_rollback()
+    }
+}
+
+/// The results of rolling back the guards of a [`RollbackStack`], see
+/// [`RollbackStack::run_and_report`].
+///
+/// The results are in execution order. If the stack stopped on the first failure, see
+/// [`RollbackStack::with_failure_mode`], only the guards up to the failure ran. The report holds
+/// the guards that did not run until they are taken via [`Self::take_not_run`]. Guards that are
+/// not taken are dismissed when the report is dropped (see [`RollbackGuard::ok`]).
+pub struct RollbackReport<'a, T, E>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    results: Vec<Result<T, E>>,
+    not_run: Vec<RollbackGuard<'a, T, E>>,
+}
+
+impl<'a, T, E> RollbackReport<'a, T, E>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    /// Iterates over the results of all guards.
+    pub fn iter(&self) -> std::slice::Iter<'_, Result<T, E>> {
+        self.results.iter()
+    }
+
+    /// Iterates over the results of all guards that were rolled back successfully.
+    pub fn successes(&self) -> impl Iterator<Item = &T> {
+        self.results
+            .iter()
+            .filter_map(|result| result.as_ref().ok())
+    }
+
+    /// Iterates over the errors of all guards that failed to roll back.
+    pub fn failures(&self) -> impl Iterator<Item = &E> {
+        self.results
+            .iter()
+            .filter_map(|result| result.as_ref().err())
+    }
+
+    /// The number of guards that were rolled back.
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Returns `true` if the stack contained no guards.
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    /// The number of guards that did not run, because the stack stopped on the first failure,
+    /// see [`StackFailureMode::StopOnFirstError`]. Guards taken via [`Self::take_not_run`] are
+    /// not counted anymore.
+    pub fn not_run(&self) -> usize {
+        self.not_run.len()
+    }
+
+    /// Takes the guards that did not run out of the report, in execution order.
+    ///
+    /// The guards are still armed, so dropping them rolls them back; [`RollbackGuard::ok`]
+    /// dismisses them.
+    ///
+    /// ```rust
+    /// use transaction_rollback::{
+    ///     rollback, Rollback, RollbackError, RollbackStack, StackFailureMode,
+    /// };
+    /// # #[derive(Debug, thiserror::Error)]
+    /// # #[error("{0}")]
+    /// # struct ExampleError(&'static str);
+    ///
+    /// let mut stack =
+    ///     RollbackStack::default().with_failure_mode(StackFailureMode::StopOnFirstError);
+    /// stack.push(rollback(|| Ok("remove directory")));
+    /// stack.push(rollback(|| Err(RollbackError(ExampleError("can not unmount")))));
+    /// let mut report = stack.run_and_report();
+    ///
+    /// // Retry the remaining guards after the failure was dealt with.
+    /// let guards = report.take_not_run();
+    /// let retried: Result<Vec<_>, _> = guards.into_iter().map(Rollback::do_rollback).collect();
+    /// assert_eq!(vec!["remove directory"], retried.unwrap());
+    /// assert_eq!(0, report.not_run());
+    /// ```
+    pub fn take_not_run(&mut self) -> Vec<RollbackGuard<'a, T, E>> {
+        mem::take(&mut self.not_run)
+    }
+}
+
+impl<'a, T, E> Debug for RollbackReport<'a, T, E>
+where
+    T: Debug,
+    E: MaybeError + Debug,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RollbackReport")
+            .field("results", &self.results)
+            .field("not_run", &self.not_run.len())
+            .finish()
+    }
+}
+
+impl<'a, T, E> Drop for RollbackReport<'a, T, E>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    fn drop(&mut self) {
+        self.take_not_run().into_iter().for_each(RollbackGuard::ok);
+    }
+}
+
+impl<'a, T, E> IntoIterator for RollbackReport<'a, T, E>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    type Item = Result<T, E>;
+    type IntoIter = std::vec::IntoIter<Result<T, E>>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        mem::take(&mut self.results).into_iter()
+    }
+}
+
+impl<'r, 'a, T, E> IntoIterator for &'r RollbackReport<'a, T, E>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    type Item = &'r Result<T, E>;
+    type IntoIter = std::slice::Iter<'r, Result<T, E>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.results.iter()
+    }
+}
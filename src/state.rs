@@ -0,0 +1,671 @@
+use crate::transaction::with_finally_error;
+use crate::TransactionState;
+use std::error::Error;
+use std::mem;
+
+/// One or two values, coming from the first, the second or both of two combined items.
+///
+/// This is used by [`TransactionState::combine`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Combined<A, B> {
+    /// Only the first item had a value.
+    First(A),
+    /// Only the second item had a value.
+    Second(B),
+    /// Both items had a value.
+    Both(A, B),
+}
+
+impl<A, B> Combined<A, B> {
+    /// Builds a `Combined` out of two optional values. Returns `None` if neither is set.
+    pub fn from_options(first: Option<A>, second: Option<B>) -> Option<Self> {
+        match (first, second) {
+            (Some(a), Some(b)) => Some(Self::Both(a, b)),
+            (Some(a), None) => Some(Self::First(a)),
+            (None, Some(b)) => Some(Self::Second(b)),
+            (None, None) => None,
+        }
+    }
+
+    /// The value of the first item, if any.
+    pub fn first(&self) -> Option<&A> {
+        match self {
+            Self::First(a) | Self::Both(a, _) => Some(a),
+            Self::Second(_) => None,
+        }
+    }
+
+    /// The value of the second item, if any.
+    pub fn second(&self) -> Option<&B> {
+        match self {
+            Self::Second(b) | Self::Both(_, b) => Some(b),
+            Self::First(_) => None,
+        }
+    }
+}
+
+impl<BE, O, E, RO, RE, FE> TransactionState<BE, O, E, RO, RE, FE> {
+    /// Creates a [`TransactionState::FailedBefore`].
+    ///
+    /// These constructors are equivalent to the variants, but can be more convenient to use,
+    /// for example when building expected states in tests:
+    ///
+    /// ```rust
+    /// use transaction_rollback::{simple_transaction, Transaction, TransactionState};
+    ///
+    /// let state = simple_transaction(|| Err::<(), _>("op"), |_| Ok::<_, ()>(())).execute();
+    /// assert_eq!(TransactionState::rollback("op", Ok(())), state);
+    /// ```
+    pub fn failed_before(err: BE) -> Self {
+        TransactionState::FailedBefore(err)
+    }
+
+    /// Creates a [`TransactionState::Ok`], see [`Self::failed_before`].
+    pub fn ok(value: O) -> Self {
+        TransactionState::Ok(value)
+    }
+
+    /// Creates a [`TransactionState::Rollback`], see [`Self::failed_before`].
+    pub fn rollback(err: E, rollback_result: Result<RO, RE>) -> Self {
+        TransactionState::Rollback(err, rollback_result)
+    }
+
+    /// Creates a [`TransactionState::OkButFailedFinally`], see [`Self::failed_before`].
+    pub fn ok_but_failed_finally(value: O, finally_err: FE) -> Self {
+        TransactionState::OkButFailedFinally(value, finally_err)
+    }
+
+    /// Creates a [`TransactionState::RollbackButFailedFinally`], see [`Self::failed_before`].
+    pub fn rollback_but_failed_finally(
+        err: E,
+        rollback_result: Result<RO, RE>,
+        finally_err: FE,
+    ) -> Self {
+        TransactionState::RollbackButFailedFinally(err, rollback_result, finally_err)
+    }
+
+    /// Creates a [`TransactionState::Skipped`], see [`Self::failed_before`].
+    pub fn skipped(reason: BE) -> Self {
+        TransactionState::Skipped(reason)
+    }
+
+    /// Creates a [`TransactionState::FailedNoRollback`], see [`Self::failed_before`].
+    pub fn failed_no_rollback(err: E) -> Self {
+        TransactionState::FailedNoRollback(err)
+    }
+
+    /// Creates a [`TransactionState::FailedNoRollbackButFailedFinally`], see
+    /// [`Self::failed_before`].
+    pub fn failed_no_rollback_but_failed_finally(err: E, finally_err: FE) -> Self {
+        TransactionState::FailedNoRollbackButFailedFinally(err, finally_err)
+    }
+
+    /// Combines the states of two transactions into one state.
+    ///
+    /// The rules are as follows, in order of precedence:
+    ///
+    /// 1. If any of the states is [`TransactionState::FailedBefore`], the result is
+    ///    `FailedBefore` with the errors of all states that failed before. All other payloads
+    ///    are discarded.
+    /// 2. If any of the states is [`TransactionState::Skipped`], the result is `Skipped` with
+    ///    the reasons of all skipped states. All other payloads are discarded.
+    /// 3. If any of the states rolled back ([`TransactionState::Rollback`] or
+    ///    [`TransactionState::RollbackButFailedFinally`]), the result is `Rollback` with the
+    ///    operation errors of all states whose operation failed. The rollback result is `Ok` with
+    ///    the rollback values if all rollbacks succeeded, otherwise it is `Err` with the errors of
+    ///    all failed rollbacks (and the values of the successful ones are discarded). The `Ok`
+    ///    value of a state whose operation succeeded is discarded.
+    /// 4. If any of the states failed without a rollback ([`TransactionState::FailedNoRollback`]
+    ///    or [`TransactionState::FailedNoRollbackButFailedFinally`]), the result is
+    ///    `FailedNoRollback` with the operation errors of all such states. The `Ok` value of a
+    ///    state whose operation succeeded is discarded.
+    /// 5. Otherwise both states succeeded, and the result is `Ok` with both `Ok` values.
+    ///
+    /// In cases 3. to 5., if `finally` failed for any of the states, the result is the
+    /// corresponding `...ButFailedFinally` variant, with the finally errors of all states that
+    /// failed `finally`.
+    ///
+    /// ```rust
+    /// use transaction_rollback::{Combined, TransactionState};
+    /// type State = TransactionState<&'static str, u8, &'static str, (), &'static str, &'static str>;
+    ///
+    /// let ok: State = TransactionState::Ok(1);
+    /// let ok_but_finally: State = TransactionState::OkButFailedFinally(2, "finally");
+    /// let rollback: State = TransactionState::Rollback("op", Ok(()));
+    /// let failed_rollback: State = TransactionState::Rollback("op", Err("rollback"));
+    /// let before: State = TransactionState::FailedBefore("before");
+    ///
+    /// assert_eq!(TransactionState::Ok((1, 1)), ok.clone().combine(ok.clone()));
+    /// assert_eq!(
+    ///     TransactionState::OkButFailedFinally((1, 2), Combined::Second("finally")),
+    ///     ok.clone().combine(ok_but_finally.clone()),
+    /// );
+    /// assert_eq!(
+    ///     TransactionState::RollbackButFailedFinally(
+    ///         Combined::Second("op"),
+    ///         Ok(Combined::Second(())),
+    ///         Combined::First("finally"),
+    ///     ),
+    ///     ok_but_finally.clone().combine(rollback.clone()),
+    /// );
+    /// assert_eq!(
+    ///     TransactionState::Rollback(
+    ///         Combined::Both("op", "op"),
+    ///         Err(Combined::Second("rollback")),
+    ///     ),
+    ///     rollback.clone().combine(failed_rollback.clone()),
+    /// );
+    /// assert_eq!(
+    ///     TransactionState::FailedBefore(Combined::First("before")),
+    ///     before.clone().combine(failed_rollback.clone()),
+    /// );
+    /// assert_eq!(
+    ///     TransactionState::FailedBefore(Combined::Both("before", "before")),
+    ///     before.clone().combine(before.clone()),
+    /// );
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn combine<BE2, O2, E2, RO2, RE2, FE2>(
+        self,
+        other: TransactionState<BE2, O2, E2, RO2, RE2, FE2>,
+    ) -> TransactionState<
+        Combined<BE, BE2>,
+        (O, O2),
+        Combined<E, E2>,
+        Combined<RO, RO2>,
+        Combined<RE, RE2>,
+        Combined<FE, FE2>,
+    > {
+        let (before_1, skipped_1, ok_1, rollback_1, finally_1) = self.into_parts();
+        let (before_2, skipped_2, ok_2, rollback_2, finally_2) = other.into_parts();
+
+        if let Some(before) = Combined::from_options(before_1, before_2) {
+            return TransactionState::FailedBefore(before);
+        }
+        if let Some(skipped) = Combined::from_options(skipped_1, skipped_2) {
+            return TransactionState::Skipped(skipped);
+        }
+        let finally = Combined::from_options(finally_1, finally_2);
+
+        match (ok_1, ok_2) {
+            (Some(ok_1), Some(ok_2)) => match finally {
+                None => TransactionState::Ok((ok_1, ok_2)),
+                Some(fe) => TransactionState::OkButFailedFinally((ok_1, ok_2), fe),
+            },
+            _ => {
+                let (err_1, result_1) = rollback_1.unzip();
+                let (err_2, result_2) = rollback_2.unzip();
+                let (result_1, result_2) = (result_1.flatten(), result_2.flatten());
+                let err = Combined::from_options(err_1, err_2)
+                    .expect("at least one of the states must have failed");
+                if result_1.is_none() && result_2.is_none() {
+                    return match finally {
+                        None => TransactionState::FailedNoRollback(err),
+                        Some(fe) => TransactionState::FailedNoRollbackButFailedFinally(err, fe),
+                    };
+                }
+                let (rollback_ok_1, rollback_err_1) = split_result(result_1);
+                let (rollback_ok_2, rollback_err_2) = split_result(result_2);
+                let result = match Combined::from_options(rollback_err_1, rollback_err_2) {
+                    Some(re) => Err(re),
+                    None => Ok(Combined::from_options(rollback_ok_1, rollback_ok_2)
+                        .expect("at least one of the states must have rolled back")),
+                };
+                match finally {
+                    None => TransactionState::Rollback(err, result),
+                    Some(fe) => TransactionState::RollbackButFailedFinally(err, result, fe),
+                }
+            }
+        }
+    }
+
+    /// Splits the state into the before error, the skip reason, the `Ok` value, the operation
+    /// error and rollback result and the finally error.
+    #[allow(clippy::type_complexity)]
+    fn into_parts(
+        self,
+    ) -> (
+        Option<BE>,
+        Option<BE>,
+        Option<O>,
+        Option<(E, Option<Result<RO, RE>>)>,
+        Option<FE>,
+    ) {
+        match self {
+            TransactionState::FailedBefore(be) => (Some(be), None, None, None, None),
+            TransactionState::Skipped(reason) => (None, Some(reason), None, None, None),
+            TransactionState::Ok(o) => (None, None, Some(o), None, None),
+            TransactionState::Rollback(e, r) => (None, None, None, Some((e, Some(r))), None),
+            TransactionState::OkButFailedFinally(o, fe) => (None, None, Some(o), None, Some(fe)),
+            TransactionState::RollbackButFailedFinally(e, r, fe) => {
+                (None, None, None, Some((e, Some(r))), Some(fe))
+            }
+            TransactionState::FailedNoRollback(e) => (None, None, None, Some((e, None)), None),
+            TransactionState::FailedNoRollbackButFailedFinally(e, fe) => {
+                (None, None, None, Some((e, None)), Some(fe))
+            }
+        }
+    }
+
+    /// Returns `true` if both states are the same variant, regardless of their values.
+    ///
+    /// This is useful in tests, to assert the outcome of a transaction without depending on the
+    /// exact values. Together with [`Self::variant_name`] this gives readable failure messages:
+    ///
+    /// ```rust
+    /// use transaction_rollback::TransactionState;
+    /// type State = TransactionState<(), (), &'static str, (), (), ()>;
+    ///
+    /// let state: State = TransactionState::Rollback("connection reset", Ok(()));
+    /// let expected: State = TransactionState::Rollback("", Ok(()));
+    /// assert!(
+    ///     state.same_variant(&expected),
+    ///     "expected {}, got {}",
+    ///     expected.variant_name(),
+    ///     state.variant_name()
+    /// );
+    /// assert!(!state.same_variant(&TransactionState::Ok(())));
+    /// ```
+    pub fn same_variant(&self, other: &Self) -> bool {
+        mem::discriminant(self) == mem::discriminant(other)
+    }
+
+    /// The name of the variant of this state, for example `"RollbackButFailedFinally"`.
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            TransactionState::FailedBefore(_) => "FailedBefore",
+            TransactionState::Ok(_) => "Ok",
+            TransactionState::Rollback(_, _) => "Rollback",
+            TransactionState::OkButFailedFinally(_, _) => "OkButFailedFinally",
+            TransactionState::RollbackButFailedFinally(_, _, _) => "RollbackButFailedFinally",
+            TransactionState::Skipped(_) => "Skipped",
+            TransactionState::FailedNoRollback(_) => "FailedNoRollback",
+            TransactionState::FailedNoRollbackButFailedFinally(_, _) => {
+                "FailedNoRollbackButFailedFinally"
+            }
+        }
+    }
+
+    /// The exit code of [`Self::exit_code`] for [`TransactionState::Ok`] and
+    /// [`TransactionState::Skipped`].
+    pub const EXIT_SUCCESS: i32 = 0;
+    /// The exit code of [`Self::exit_code`] for [`TransactionState::FailedBefore`].
+    pub const EXIT_FAILED_BEFORE: i32 = 2;
+    /// The exit code of [`Self::exit_code`] for a failed operation that was rolled back
+    /// successfully.
+    pub const EXIT_ROLLBACK: i32 = 3;
+    /// The exit code of [`Self::exit_code`] for a failed operation whose rollback failed as
+    /// well.
+    pub const EXIT_ROLLBACK_FAILED: i32 = 4;
+    /// The exit code of [`Self::exit_code`] for a failed operation that was not rolled back.
+    pub const EXIT_FAILED_NO_ROLLBACK: i32 = 5;
+    /// The exit code of [`Self::exit_code`] for [`TransactionState::OkButFailedFinally`].
+    pub const EXIT_FAILED_FINALLY: i32 = 6;
+
+    /// Maps the state to a process exit code, for command line tools.
+    ///
+    /// The codes are the associated constants of this type:
+    ///
+    /// - [`Self::EXIT_SUCCESS`] (`0`) for [`TransactionState::Ok`] and
+    ///   [`TransactionState::Skipped`].
+    /// - [`Self::EXIT_FAILED_BEFORE`] (`2`) for [`TransactionState::FailedBefore`].
+    /// - [`Self::EXIT_ROLLBACK`] (`3`) if the operation failed and was rolled back successfully.
+    /// - [`Self::EXIT_ROLLBACK_FAILED`] (`4`) if the operation failed and the rollback failed.
+    /// - [`Self::EXIT_FAILED_NO_ROLLBACK`] (`5`) if the operation failed and was not rolled back.
+    /// - [`Self::EXIT_FAILED_FINALLY`] (`6`) for [`TransactionState::OkButFailedFinally`].
+    ///
+    /// If both the operation and `finally` failed, the code is determined by the failure of the
+    /// operation, so the `...ButFailedFinally` variants of failed operations have the same code
+    /// as the variants without a failed `finally`. `1` is not used, so it stays available for
+    /// other errors of the tool.
+    ///
+    /// ```rust
+    /// use transaction_rollback::TransactionState;
+    /// type State = TransactionState<(), (), (), (), (), ()>;
+    ///
+    /// assert_eq!(State::EXIT_SUCCESS, State::Ok(()).exit_code());
+    /// assert_eq!(State::EXIT_ROLLBACK, State::Rollback((), Ok(())).exit_code());
+    /// assert_eq!(
+    ///     State::EXIT_ROLLBACK_FAILED,
+    ///     State::RollbackButFailedFinally((), Err(()), ()).exit_code()
+    /// );
+    /// // In a command line tool:
+    /// // std::process::exit(state.exit_code());
+    /// ```
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            TransactionState::Ok(_) | TransactionState::Skipped(_) => Self::EXIT_SUCCESS,
+            TransactionState::FailedBefore(_) => Self::EXIT_FAILED_BEFORE,
+            TransactionState::Rollback(_, Ok(_))
+            | TransactionState::RollbackButFailedFinally(_, Ok(_), _) => Self::EXIT_ROLLBACK,
+            TransactionState::Rollback(_, Err(_))
+            | TransactionState::RollbackButFailedFinally(_, Err(_), _) => {
+                Self::EXIT_ROLLBACK_FAILED
+            }
+            TransactionState::FailedNoRollback(_)
+            | TransactionState::FailedNoRollbackButFailedFinally(_, _) => {
+                Self::EXIT_FAILED_NO_ROLLBACK
+            }
+            TransactionState::OkButFailedFinally(_, _) => Self::EXIT_FAILED_FINALLY,
+        }
+    }
+
+    /// Returns `true` if the transaction was skipped, see [`TransactionState::Skipped`].
+    pub fn is_skipped(&self) -> bool {
+        matches!(self, TransactionState::Skipped(_))
+    }
+
+    /// The reason the transaction was skipped, if it was skipped.
+    pub fn skip_reason(&self) -> Option<&BE> {
+        match self {
+            TransactionState::Skipped(reason) => Some(reason),
+            _ => None,
+        }
+    }
+
+    /// Converts the state into a nested `Result`, together with the error of `finally`.
+    ///
+    /// The outer `Result` is `Err` with the before error if the transaction failed before or
+    /// was skipped. Otherwise, the inner `Result` is `Ok` with the `Ok` value if the operation
+    /// succeeded, or `Err` with a [`FailedOperation`] if it failed. It holds the operation error
+    /// and the result of the rollback, which is `None` if the transaction was not rolled back.
+    /// The error of `finally` is returned next to the nested `Result`, it is `Some` for all
+    /// `...ButFailedFinally` states.
+    ///
+    /// The state can be converted back via [`From`], which also provides an infallible
+    /// [`TryFrom`]. This restores the state, except that a skipped transaction becomes
+    /// [`TransactionState::FailedBefore`].
+    ///
+    /// ```rust
+    /// use transaction_rollback::{FailedOperation, TransactionState};
+    /// type State = TransactionState<&'static str, u8, &'static str, (), (), &'static str>;
+    ///
+    /// let ok_but_finally: State = TransactionState::OkButFailedFinally(42, "finally");
+    /// assert_eq!((Ok(Ok(42)), Some("finally")), ok_but_finally.into_nested_result());
+    ///
+    /// let rollback: State = TransactionState::Rollback("op", Err(()));
+    /// let nested = rollback.into_nested_result();
+    /// assert_eq!(
+    ///     (
+    ///         Ok(Err(FailedOperation {
+    ///             error: "op",
+    ///             rollback_result: Some(Err(()))
+    ///         })),
+    ///         None
+    ///     ),
+    ///     nested
+    /// );
+    /// assert_eq!(State::Rollback("op", Err(())), State::from(nested));
+    ///
+    /// let before: State = TransactionState::FailedBefore("before");
+    /// assert_eq!((Err("before"), None), before.into_nested_result());
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn into_nested_result(
+        self,
+    ) -> (
+        Result<Result<O, FailedOperation<E, RO, RE>>, BE>,
+        Option<FE>,
+    ) {
+        let failed = |error, rollback_result| {
+            Ok(Err(FailedOperation {
+                error,
+                rollback_result,
+            }))
+        };
+        match self {
+            TransactionState::FailedBefore(be) | TransactionState::Skipped(be) => (Err(be), None),
+            TransactionState::Ok(o) => (Ok(Ok(o)), None),
+            TransactionState::OkButFailedFinally(o, fe) => (Ok(Ok(o)), Some(fe)),
+            TransactionState::Rollback(e, r) => (failed(e, Some(r)), None),
+            TransactionState::RollbackButFailedFinally(e, r, fe) => (failed(e, Some(r)), Some(fe)),
+            TransactionState::FailedNoRollback(e) => (failed(e, None), None),
+            TransactionState::FailedNoRollbackButFailedFinally(e, fe) => {
+                (failed(e, None), Some(fe))
+            }
+        }
+    }
+
+    /// Chains another step onto a successful transaction.
+    ///
+    /// If the state is [`TransactionState::Ok`] or [`TransactionState::OkButFailedFinally`], `f`
+    /// is called with the `Ok` value and its state is returned. All other states are returned
+    /// unchanged and `f` is not called.
+    ///
+    /// For `OkButFailedFinally`, the error of `finally` is kept by turning the state returned by
+    /// `f` into the corresponding `...ButFailedFinally` variant. If that state already has an
+    /// error of `finally`, or if it is [`TransactionState::FailedBefore`] or
+    /// [`TransactionState::Skipped`], it is returned unchanged and the earlier error of
+    /// `finally` is discarded.
+    ///
+    /// ```rust
+    /// use transaction_rollback::TransactionState;
+    /// type State<O> = TransactionState<(), O, &'static str, (), (), &'static str>;
+    ///
+    /// let parse = |s: &'static str| -> State<u8> {
+    ///     match s.parse() {
+    ///         Ok(n) => TransactionState::Ok(n),
+    ///         Err(_) => TransactionState::FailedNoRollback("not a number"),
+    ///     }
+    /// };
+    ///
+    /// let ok: State<_> = TransactionState::Ok("42");
+    /// assert_eq!(TransactionState::Ok(42), ok.and_then(parse));
+    ///
+    /// let ok_but_finally: State<_> = TransactionState::OkButFailedFinally("foo", "finally");
+    /// assert_eq!(
+    ///     TransactionState::FailedNoRollbackButFailedFinally("not a number", "finally"),
+    ///     ok_but_finally.and_then(parse),
+    /// );
+    ///
+    /// let rollback: State<_> = TransactionState::Rollback("op", Ok(()));
+    /// assert_eq!(TransactionState::Rollback("op", Ok(())), rollback.and_then(parse));
+    /// ```
+    pub fn and_then<O2, F>(self, f: F) -> TransactionState<BE, O2, E, RO, RE, FE>
+    where
+        F: FnOnce(O) -> TransactionState<BE, O2, E, RO, RE, FE>,
+    {
+        match self {
+            TransactionState::Ok(o) => f(o),
+            TransactionState::OkButFailedFinally(o, fe) => match f(o) {
+                TransactionState::Ok(o2) => TransactionState::OkButFailedFinally(o2, fe),
+                TransactionState::Rollback(e, r) => {
+                    TransactionState::RollbackButFailedFinally(e, r, fe)
+                }
+                TransactionState::FailedNoRollback(e) => {
+                    TransactionState::FailedNoRollbackButFailedFinally(e, fe)
+                }
+                state => state,
+            },
+            TransactionState::FailedBefore(be) => TransactionState::FailedBefore(be),
+            TransactionState::Rollback(e, r) => TransactionState::Rollback(e, r),
+            TransactionState::RollbackButFailedFinally(e, r, fe) => {
+                TransactionState::RollbackButFailedFinally(e, r, fe)
+            }
+            TransactionState::Skipped(reason) => TransactionState::Skipped(reason),
+            TransactionState::FailedNoRollback(e) => TransactionState::FailedNoRollback(e),
+            TransactionState::FailedNoRollbackButFailedFinally(e, fe) => {
+                TransactionState::FailedNoRollbackButFailedFinally(e, fe)
+            }
+        }
+    }
+}
+
+impl<BE, O, E, RO, RE, FE> TransactionState<BE, O, E, RO, RE, FE>
+where
+    BE: Error,
+    E: Error,
+    RE: Error,
+    FE: Error,
+{
+    /// Returns the most relevant error of the state, if anything failed.
+    ///
+    /// - For [`TransactionState::FailedBefore`] this is the before error.
+    /// - For [`TransactionState::Rollback`] and [`TransactionState::RollbackButFailedFinally`]
+    ///   this is the error of the rollback if it failed, since the operation may then be only
+    ///   partially undone. Otherwise it is the operation error.
+    /// - For [`TransactionState::FailedNoRollback`] and
+    ///   [`TransactionState::FailedNoRollbackButFailedFinally`] this is the operation error.
+    /// - For [`TransactionState::OkButFailedFinally`] this is the error of `finally`.
+    ///
+    /// That is, the error of `finally` is only returned if nothing else failed. For
+    /// [`TransactionState::Ok`] and [`TransactionState::Skipped`] this returns `None`.
+    ///
+    /// ```rust
+    /// use transaction_rollback::TransactionState;
+    /// # #[derive(Debug, thiserror::Error)]
+    /// # #[error("{0}")]
+    /// # struct ExampleError(&'static str);
+    /// type State = TransactionState<ExampleError, (), ExampleError, (), ExampleError, ExampleError>;
+    ///
+    /// let state: State = TransactionState::RollbackButFailedFinally(
+    ///     ExampleError("operation"),
+    ///     Err(ExampleError("rollback")),
+    ///     ExampleError("finally"),
+    /// );
+    /// assert_eq!("rollback", state.any_error().unwrap().to_string());
+    ///
+    /// let state: State = TransactionState::OkButFailedFinally((), ExampleError("finally"));
+    /// assert_eq!("finally", state.any_error().unwrap().to_string());
+    ///
+    /// let state: State = TransactionState::Ok(());
+    /// assert!(state.any_error().is_none());
+    /// ```
+    pub fn any_error(&self) -> Option<&(dyn Error + '_)> {
+        match self {
+            TransactionState::FailedBefore(be) => Some(be),
+            TransactionState::Ok(_) | TransactionState::Skipped(_) => None,
+            TransactionState::Rollback(_, Err(re))
+            | TransactionState::RollbackButFailedFinally(_, Err(re), _) => Some(re),
+            TransactionState::Rollback(e, Ok(_))
+            | TransactionState::RollbackButFailedFinally(e, Ok(_), _)
+            | TransactionState::FailedNoRollback(e)
+            | TransactionState::FailedNoRollbackButFailedFinally(e, _) => Some(e),
+            TransactionState::OkButFailedFinally(_, fe) => Some(fe),
+        }
+    }
+}
+
+impl<O, RO>
+    TransactionState<
+        Box<dyn Error + Send + Sync>,
+        O,
+        Box<dyn Error + Send + Sync>,
+        RO,
+        Box<dyn Error + Send + Sync>,
+        Box<dyn Error + Send + Sync>,
+    >
+{
+    /// Returns the first error of the state that is an `X`, for states of transactions whose
+    /// errors are type-erased.
+    ///
+    /// All errors held by the state are tried, the most relevant one first, see
+    /// [`Self::any_error`]. This allows handling specific errors of type-erased transactions,
+    /// for example of heterogeneous collections of transactions. Returns `None` if no error is
+    /// an `X`, or if nothing failed.
+    ///
+    /// ```rust
+    /// use std::error::Error;
+    /// use transaction_rollback::TransactionState;
+    /// # #[derive(Debug, thiserror::Error)]
+    /// # #[error("{0}")]
+    /// # struct ExampleError(&'static str);
+    /// # #[derive(Debug, thiserror::Error)]
+    /// # #[error("not found")]
+    /// # struct NotFound;
+    /// type Boxed = Box<dyn Error + Send + Sync>;
+    /// type State = TransactionState<Boxed, (), Boxed, (), Boxed, Boxed>;
+    ///
+    /// let state: State = TransactionState::RollbackButFailedFinally(
+    ///     Box::new(NotFound),
+    ///     Ok(()),
+    ///     Box::new(ExampleError("finally")),
+    /// );
+    /// assert!(state.downcast_error_ref::<NotFound>().is_some());
+    /// assert_eq!("finally", state.downcast_error_ref::<ExampleError>().unwrap().0);
+    ///
+    /// let state: State = TransactionState::FailedNoRollback(Box::new(ExampleError("operation")));
+    /// assert!(state.downcast_error_ref::<NotFound>().is_none());
+    /// ```
+    pub fn downcast_error_ref<X>(&self) -> Option<&X>
+    where
+        X: Error + 'static,
+    {
+        // Ordered like `any_error`: a failed rollback, then the operation and finally `finally`.
+        let errors: [Option<&Box<dyn Error + Send + Sync>>; 3] = match self {
+            TransactionState::FailedBefore(be) => [Some(be), None, None],
+            TransactionState::Ok(_) | TransactionState::Skipped(_) => [None, None, None],
+            TransactionState::Rollback(e, r) => [r.as_ref().err(), Some(e), None],
+            TransactionState::RollbackButFailedFinally(e, r, fe) => {
+                [r.as_ref().err(), Some(e), Some(fe)]
+            }
+            TransactionState::FailedNoRollback(e) => [Some(e), None, None],
+            TransactionState::FailedNoRollbackButFailedFinally(e, fe) => [Some(e), Some(fe), None],
+            TransactionState::OkButFailedFinally(_, fe) => [Some(fe), None, None],
+        };
+        errors.into_iter().flatten().find_map(|e| e.downcast_ref())
+    }
+}
+
+/// Converts a nested `Result` and the error of `finally`, see
+/// [`TransactionState::into_nested_result`].
+#[allow(clippy::type_complexity)]
+impl<BE, O, E, RO, RE, FE>
+    From<(
+        Result<Result<O, FailedOperation<E, RO, RE>>, BE>,
+        Option<FE>,
+    )> for TransactionState<BE, O, E, RO, RE, FE>
+{
+    fn from(
+        (result, finally_error): (
+            Result<Result<O, FailedOperation<E, RO, RE>>, BE>,
+            Option<FE>,
+        ),
+    ) -> Self {
+        let state = match result {
+            Ok(Ok(o)) => TransactionState::Ok(o),
+            Ok(Err(FailedOperation {
+                error,
+                rollback_result: Some(r),
+            })) => TransactionState::Rollback(error, r),
+            Ok(Err(FailedOperation {
+                error,
+                rollback_result: None,
+            })) => TransactionState::FailedNoRollback(error),
+            // `finally` does not run if `before` failed.
+            Err(be) => return TransactionState::FailedBefore(be),
+        };
+        match finally_error {
+            Some(fe) => with_finally_error(state, fe),
+            None => state,
+        }
+    }
+}
+
+/// A failed operation, as returned by [`TransactionState::into_nested_result`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedOperation<E, RO, RE> {
+    /// The error of the operation.
+    pub error: E,
+    /// The result of the rollback, or `None` if the transaction was not rolled back.
+    pub rollback_result: Option<Result<RO, RE>>,
+}
+
+/// A state of a transaction bundled with its diagnostic context.
+///
+/// This is returned by [`crate::Transaction::execute_with_context`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextualState<S> {
+    /// The state of the transaction.
+    pub state: S,
+    /// The context of the transaction, see [`crate::Transaction::context`].
+    pub context: Option<String>,
+}
+
+fn split_result<T, E>(result: Option<Result<T, E>>) -> (Option<T>, Option<E>) {
+    match result {
+        Some(Ok(v)) => (Some(v), None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    }
+}
@@ -0,0 +1,89 @@
+use crate::rollback::private;
+use crate::{MaybeError, RollbackGuard};
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// A flag for testing whether a rollback action ran, see [`assert_rolls_back_on_drop`].
+///
+/// Clones share the same flag. Only available with the `test-util` feature.
+#[derive(Debug, Clone, Default)]
+pub struct RollbackProbe {
+    ran: Rc<Cell<bool>>,
+}
+
+impl RollbackProbe {
+    /// Records that the rollback action ran. Call this from the action under test.
+    pub fn mark(&self) {
+        self.ran.set(true);
+    }
+
+    /// Returns `true` if [`Self::mark`] was called on this probe or one of its clones.
+    pub fn was_marked(&self) -> bool {
+        self.ran.get()
+    }
+}
+
+/// Asserts that the guard created by `make_guard` rolls back when it is dropped.
+///
+/// `make_guard` is passed a [`RollbackProbe`], which the rollback action must
+/// [`mark`](RollbackProbe::mark). The guard is then dropped normally, outside of a panic, and
+/// the probe must have been marked by that. The guard can be any type that rolls back on drop,
+/// for example a [`RollbackGuard`] or a [`crate::RollbackStack`].
+///
+/// Only available with the `test-util` feature.
+///
+/// # Panics
+/// If the probe was not marked by dropping the guard, or already before.
+///
+/// ```rust
+/// use transaction_rollback::{assert_rolls_back_on_drop, infallible_rollback};
+///
+/// assert_rolls_back_on_drop(|probe| infallible_rollback(move || probe.mark()));
+/// ```
+pub fn assert_rolls_back_on_drop<G, F>(make_guard: F)
+where
+    F: FnOnce(RollbackProbe) -> G,
+{
+    let probe = RollbackProbe::default();
+    let guard = make_guard(probe.clone());
+    assert!(
+        !probe.was_marked(),
+        "transaction-rollback: the rollback ran before the guard was dropped."
+    );
+    drop(guard);
+    assert!(
+        probe.was_marked(),
+        "transaction-rollback: the rollback did not run when the guard was dropped."
+    );
+}
+
+/// Asserts that the guard created by `make_guard` does not roll back after
+/// [`RollbackGuard::ok`].
+///
+/// This works like [`assert_rolls_back_on_drop`], but calls [`RollbackGuard::ok`] on the guard
+/// instead of dropping it, after which the probe must not have been marked.
+///
+/// Only available with the `test-util` feature.
+///
+/// # Panics
+/// If the probe was marked.
+///
+/// ```rust
+/// use transaction_rollback::{assert_not_rolled_back_after_ok, infallible_rollback};
+///
+/// assert_not_rolled_back_after_ok(|probe| infallible_rollback(move || probe.mark()));
+/// ```
+pub fn assert_not_rolled_back_after_ok<'a, T, E, F>(make_guard: F)
+where
+    F: FnOnce(RollbackProbe) -> RollbackGuard<'a, T, E>,
+    T: 'a,
+    E: MaybeError + 'a,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    let probe = RollbackProbe::default();
+    make_guard(probe.clone()).ok();
+    assert!(
+        !probe.was_marked(),
+        "transaction-rollback: the rollback ran although the guard was dismissed via `ok`."
+    );
+}
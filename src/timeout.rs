@@ -0,0 +1,127 @@
+use crate::{StateOf, Transaction};
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::panic::resume_unwind;
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// A transaction whose execution is bounded by a timeout.
+///
+/// [`Timeout::execute`] executes the transaction on a new thread and waits for it at most
+/// for the given duration. If it does not finish in time, [`TimedOut`] is returned.
+///
+/// Note that the transaction can not be interrupted: After a timeout it keeps running on its
+/// thread until it finishes, including its rollback and `finally`, and keeps holding all
+/// resources it uses until then. Its resulting state can still be retrieved via
+/// [`TimedOut::wait`]. If the [`TimedOut`] is dropped instead, the thread is detached and its
+/// state is discarded once it finishes.
+///
+/// ```rust
+/// use std::thread;
+/// use std::time::Duration;
+/// use transaction_rollback::{simple_transaction, Timeout, TransactionState};
+///
+/// let slow = simple_transaction::<_, _, _, (), ()>(
+///     || {
+///         thread::sleep(Duration::from_millis(200));
+///         Ok(())
+///     },
+///     |_| Ok(()),
+/// );
+/// let timed_out = Timeout::new(slow, Duration::from_millis(10))
+///     .execute()
+///     .unwrap_err();
+/// // The transaction still finishes in the background.
+/// assert_eq!(TransactionState::Ok(()), timed_out.wait());
+///
+/// let fast = simple_transaction::<_, _, _, (), ()>(|| Ok(()), |_| Ok(()));
+/// let state = Timeout::new(fast, Duration::from_secs(10)).execute().unwrap();
+/// assert_eq!(TransactionState::Ok(()), state);
+/// ```
+pub struct Timeout<T> {
+    transaction: T,
+    timeout: Duration,
+}
+
+impl<T> Timeout<T>
+where
+    T: Transaction + Send + 'static,
+    StateOf<T>: Send + 'static,
+{
+    /// Wraps `transaction`, to be executed with the given timeout.
+    pub fn new(transaction: T, timeout: Duration) -> Self {
+        Self {
+            transaction,
+            timeout,
+        }
+    }
+
+    /// Executes the transaction on a new thread, see [`Transaction::execute`].
+    ///
+    /// Returns the state of the transaction if it finished within the timeout, otherwise
+    /// [`TimedOut`].
+    ///
+    /// # Panics
+    /// If the transaction panics within the timeout, the panic is resumed on the calling thread.
+    #[allow(clippy::type_complexity)]
+    pub fn execute(self) -> Result<StateOf<T>, TimedOut<StateOf<T>>> {
+        let (sender, receiver) = mpsc::channel();
+        let transaction = self.transaction;
+        let handle = thread::spawn(move || {
+            let state = transaction.execute();
+            // The receiver only waits until the timeout, afterwards the state is only returned
+            // via the join handle.
+            let _ = sender.send(());
+            state
+        });
+        match receiver.recv_timeout(self.timeout) {
+            Ok(()) | Err(RecvTimeoutError::Disconnected) => match handle.join() {
+                Ok(state) => Ok(state),
+                Err(panic) => resume_unwind(panic),
+            },
+            Err(RecvTimeoutError::Timeout) => Err(TimedOut { handle }),
+        }
+    }
+}
+
+/// The error of a [`Timeout`] whose transaction did not finish in time.
+///
+/// The transaction keeps running in the background, see [`Timeout`].
+pub struct TimedOut<S> {
+    handle: JoinHandle<S>,
+}
+
+impl<S> TimedOut<S> {
+    /// Waits for the transaction to finish and returns its state.
+    ///
+    /// # Panics
+    /// If the transaction panics, the panic is resumed on the calling thread.
+    pub fn wait(self) -> S {
+        match self.handle.join() {
+            Ok(state) => state,
+            Err(panic) => resume_unwind(panic),
+        }
+    }
+
+    /// Returns `true` if the transaction has finished in the meantime.
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+}
+
+impl<S> Debug for TimedOut<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TimedOut").finish_non_exhaustive()
+    }
+}
+
+impl<S> Display for TimedOut<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The transaction did not finish within the timeout")
+    }
+}
+
+impl<S> Error for TimedOut<S> {}
@@ -0,0 +1,152 @@
+use crate::macros::forward_transaction;
+use crate::{StateOf, Transaction, TransactionPhase};
+use std::fmt::Debug;
+
+/// A transaction that records the outcome of each of its phases.
+///
+/// [`Traced::execute`] executes the transaction like [`Transaction::execute`] and additionally
+/// returns a [`PhaseRecord`] for each phase that ran, in the order they ran. This allows
+/// reconstructing the exact path through the transaction after the fact, for example for
+/// debugging. Phases that did not run, like [`Transaction::rollback`] after a successful
+/// operation, are not recorded.
+///
+/// The error types of the transaction must implement [`Debug`], errors are recorded in their
+/// debug representation.
+///
+/// ```rust
+/// use transaction_rollback::{
+///     simple_transaction, PhaseRecord, Traced, TransactionPhase, TransactionState,
+/// };
+///
+/// let transaction = simple_transaction(|| Err::<(), _>("oh no"), |_| Ok::<_, ()>(()));
+/// let (state, records) = Traced::new(transaction).execute();
+///
+/// assert_eq!(TransactionState::Rollback("oh no", Ok(())), state);
+/// assert_eq!(
+///     vec![
+///         PhaseRecord::ok(TransactionPhase::Before),
+///         PhaseRecord::failed(TransactionPhase::Operation, "\"oh no\""),
+///         PhaseRecord::ok(TransactionPhase::Rollback),
+///         PhaseRecord::ok(TransactionPhase::Finally),
+///     ],
+///     records
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct Traced<T> {
+    transaction: T,
+}
+
+impl<T> Traced<T>
+where
+    T: Transaction,
+    T::BeforeError: Debug,
+    T::Error: Debug,
+    T::RollbackError: Debug,
+    T::FinallyError: Debug,
+{
+    /// Wraps `transaction`, to record its phases when executed.
+    pub fn new(transaction: T) -> Self {
+        Self { transaction }
+    }
+
+    /// Executes the transaction, see [`Transaction::execute`], and returns its state together
+    /// with the records of all phases that ran.
+    ///
+    /// # Panics
+    /// If executing the transaction panics, see [`Transaction::execute`].
+    pub fn execute(self) -> (StateOf<T>, Vec<PhaseRecord>) {
+        let mut records = Vec::new();
+        let state = Recording {
+            transaction: self.transaction,
+            records: &mut records,
+        }
+        .execute();
+        (state, records)
+    }
+}
+
+/// The outcome of one phase of a transaction, see [`Traced`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PhaseRecord {
+    /// The phase that ran.
+    pub phase: TransactionPhase,
+    /// The debug representation of the error of the phase, or `None` if it succeeded.
+    pub error: Option<String>,
+}
+
+impl PhaseRecord {
+    /// Creates a record of a successful phase.
+    pub fn ok(phase: TransactionPhase) -> Self {
+        Self { phase, error: None }
+    }
+
+    /// Creates a record of a failed phase, with the debug representation of its error.
+    pub fn failed(phase: TransactionPhase, error: impl Into<String>) -> Self {
+        Self {
+            phase,
+            error: Some(error.into()),
+        }
+    }
+
+    /// Returns `true` if the phase succeeded.
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// The transaction executed by [`Traced`], which records into `records`.
+struct Recording<'r, T> {
+    transaction: T,
+    records: &'r mut Vec<PhaseRecord>,
+}
+
+impl<T> Recording<'_, T> {
+    fn record<V, E: Debug>(
+        &mut self,
+        phase: TransactionPhase,
+        result: Result<V, E>,
+    ) -> Result<V, E> {
+        self.records.push(PhaseRecord {
+            phase,
+            error: result.as_ref().err().map(|e| format!("{e:?}")),
+        });
+        result
+    }
+}
+
+impl<T> Transaction for Recording<'_, T>
+where
+    T: Transaction,
+    T::BeforeError: Debug,
+    T::Error: Debug,
+    T::RollbackError: Debug,
+    T::FinallyError: Debug,
+{
+    forward_transaction! { T, transaction;
+        fn before(&mut self) -> Result<(), Self::BeforeError> {
+            let result = self.transaction.before();
+            self.record(TransactionPhase::Before, result)
+        }
+
+        fn operation(&mut self) -> Result<Self::Ok, Self::Error> {
+            let result = self.transaction.operation();
+            self.record(TransactionPhase::Operation, result)
+        }
+
+        fn validate(&mut self, ok: &Self::Ok) -> Result<(), Self::Error> {
+            let result = self.transaction.validate(ok);
+            self.record(TransactionPhase::Validate, result)
+        }
+
+        fn rollback(&mut self, err: &Self::Error) -> Result<Self::RollbackOk, Self::RollbackError> {
+            let result = self.transaction.rollback(err);
+            self.record(TransactionPhase::Rollback, result)
+        }
+
+        fn finally(&mut self, state: &StateOf<T>) -> Result<(), Self::FinallyError> {
+            let result = self.transaction.finally(state);
+            self.record(TransactionPhase::Finally, result)
+        }
+    }
+}
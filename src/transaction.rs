@@ -1,5 +1,11 @@
+use crate::depth::DepthGuard;
+use crate::macros::forward_transaction;
+use crate::{ContextualState, PhaseError, TransactionPhase};
 use std::any::Any;
+#[cfg(feature = "log")]
+use std::fmt::Debug;
 use std::panic::{catch_unwind, AssertUnwindSafe, RefUnwindSafe, UnwindSafe};
+use std::sync::Mutex;
 
 /// State of a transaction
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -23,8 +29,33 @@ pub enum TransactionState<BE, O, E, RO, RE, FE> {
     /// The second is the result of the rollback.
     /// The third is the error of `finally`.
     RollbackButFailedFinally(E, Result<RO, RE>, FE),
+    /// The transaction was not run at all, because its precondition was not met.
+    ///
+    /// The item is the reason returned by [`Transaction::precondition`]. This is only returned
+    /// by [`Transaction::execute_with_precondition`].
+    Skipped(BE),
+    /// The operation failed, but it was not rolled back, because
+    /// [`Transaction::should_rollback`] returned `false`.
+    ///
+    /// The item is the original error of the operation.
+    FailedNoRollback(E),
+    /// The operation failed and was not rolled back. Additionally `finally` failed.
+    ///
+    /// The first item is the original error of the operation, the second the error of
+    /// `finally`.
+    FailedNoRollbackButFailedFinally(E, FE),
 }
 
+/// The [`TransactionState`] that is the result of executing the transaction `T`.
+pub type StateOf<T> = TransactionState<
+    <T as Transaction>::BeforeError,
+    <T as Transaction>::Ok,
+    <T as Transaction>::Error,
+    <T as Transaction>::RollbackOk,
+    <T as Transaction>::RollbackError,
+    <T as Transaction>::FinallyError,
+>;
+
 /// A trait for an operation that can be rolled back and/or that requires to be in a certain
 /// state before/after running.
 ///
@@ -40,16 +71,25 @@ pub trait Transaction: Sized {
 
     /// Execute the transaction. This will:
     /// - First call [`Self::before`], if it fails, it's error is returned
-    ///  ([`TransactionState::FailedBefore`]).
-    /// - Otherwise it will then call [`Self::operation`], if it succeeds it will continue to
-    ///   `finally` with it's `Ok` value ([`TransactionState::Ok`]).
-    /// - Otherwise it will try to rollback by calling [`Self::rollback`]
-    /// ([`TransactionState::Rollback`]).
+    ///   ([`TransactionState::FailedBefore`]).
+    /// - Otherwise it will then call [`Self::operation`], if it succeeds its `Ok` value is
+    ///   checked with [`Self::validate`]. If that succeeds as well, it will continue to `finally`
+    ///   with it's `Ok` value ([`TransactionState::Ok`]).
+    /// - Otherwise (if either `operation` or `validate` failed) it will try to rollback by
+    ///   calling [`Self::rollback`] ([`TransactionState::Rollback`]), unless
+    ///   [`Self::should_rollback`] returns `false` for the error
+    ///   ([`TransactionState::FailedNoRollback`]).
     /// - Afterwards [`Self::finally`] will be run. If it fails either
-    /// [`TransactionState::OkButFailedFinally`] or [`TransactionState::RollbackButFailedFinally`]
-    /// are returned, otherwise the state is unchanged. `finally` is not run if `before` failed.
+    ///   [`TransactionState::OkButFailedFinally`], [`TransactionState::RollbackButFailedFinally`]
+    ///   or [`TransactionState::FailedNoRollbackButFailedFinally`] are returned, otherwise the
+    ///   state is unchanged. `finally` is not run if `before` failed or
+    ///   [`Self::skip_finally`] returns `true`.
     ///
     /// Panics are not caught, for this use [`UnwindCheckedTransaction`].
+    ///
+    /// # Panics
+    /// If executing this transaction would exceed the maximum depth of nested transactions,
+    /// see [`crate::set_max_transaction_depth`].
     #[allow(clippy::type_complexity)]
     fn execute(
         mut self,
@@ -61,30 +101,637 @@ pub trait Transaction: Sized {
         Self::RollbackError,
         Self::FinallyError,
     > {
+        let _depth = DepthGuard::enter();
         if let Err(e) = self.before() {
             TransactionState::FailedBefore(e)
         } else {
-            let state = match self.operation() {
+            let state = match self.operation().and_then(|o| self.validate(&o).map(|_| o)) {
                 Ok(o) => TransactionState::Ok(o),
-                Err(e) => {
+                Err(e) if self.should_rollback(&e) => {
                     let rollback_result = self.rollback(&e);
                     TransactionState::Rollback(e, rollback_result)
                 }
+                Err(e) => TransactionState::FailedNoRollback(e),
             };
-            if let Err(e) = self.finally(&state) {
-                match state {
-                    TransactionState::Ok(oo) => TransactionState::OkButFailedFinally(oo, e),
-                    TransactionState::Rollback(oe, rs) => {
-                        TransactionState::RollbackButFailedFinally(oe, rs, e)
-                    }
-                    _ => unreachable!(),
-                }
+            if self.skip_finally(&state) {
+                state
+            } else if let Err(e) = self.finally(&state) {
+                with_finally_error(state, e)
             } else {
                 state
             }
         }
     }
 
+    /// Like [`Self::execute`], but logs the resulting state via the [`log`] crate.
+    ///
+    /// The level depends on the state: `info` if the transaction succeeded or was skipped, `warn`
+    /// if it was rolled back successfully, not rolled back or only `finally` failed, and `error`
+    /// if `before` or the rollback failed.
+    ///
+    /// Only available with the `log` feature.
+    #[cfg(feature = "log")]
+    fn execute_logged(self) -> StateOf<Self>
+    where
+        Self::BeforeError: Debug,
+        Self::Ok: Debug,
+        Self::Error: Debug,
+        Self::RollbackOk: Debug,
+        Self::RollbackError: Debug,
+        Self::FinallyError: Debug,
+    {
+        let state = self.execute();
+        let level = match &state {
+            TransactionState::Ok(_) | TransactionState::Skipped(_) => log::Level::Info,
+            TransactionState::Rollback(_, Ok(_))
+            | TransactionState::OkButFailedFinally(..)
+            | TransactionState::FailedNoRollback(_)
+            | TransactionState::FailedNoRollbackButFailedFinally(..) => log::Level::Warn,
+            TransactionState::FailedBefore(_)
+            | TransactionState::Rollback(_, Err(_))
+            | TransactionState::RollbackButFailedFinally(..) => log::Level::Error,
+        };
+        log::log!(
+            level,
+            "transaction {} finished: {:?}",
+            std::any::type_name::<Self>(),
+            state
+        );
+        state
+    }
+
+    /// Like [`Self::execute`], but holds `lock` for the entire execution.
+    ///
+    /// The lock is acquired before [`Self::before`] and released after [`Self::finally`], so it
+    /// is held across all phases, including the rollback. This guarantees that no two
+    /// transactions executed with the same lock interleave.
+    ///
+    /// A poisoned lock is acquired anyway, since it does not protect any data.
+    ///
+    /// Note that this deadlocks if any phase of the transaction tries to acquire the same
+    /// lock, for example by executing a nested transaction with this method and the same
+    /// lock, since [`Mutex`] is not reentrant.
+    fn execute_locked(self, lock: &Mutex<()>) -> StateOf<Self> {
+        let _lock = lock.lock().unwrap_or_else(|e| e.into_inner());
+        self.execute()
+    }
+
+    /// Returns a closure that executes the transaction when called, see [`Self::execute`].
+    ///
+    /// This allows deferring the execution, for example by storing transactions of different
+    /// types together:
+    ///
+    /// ```rust
+    /// use transaction_rollback::{simple_transaction, Transaction, TransactionState};
+    ///
+    /// type State = TransactionState<(), u8, (), (), (), ()>;
+    ///
+    /// let transactions: Vec<Box<dyn FnOnce() -> State>> = vec![
+    ///     Box::new(simple_transaction(|| Ok(1), |_| Ok(())).into_fn()),
+    ///     Box::new(simple_transaction(|| Err(()), |_| Ok(())).into_fn()),
+    /// ];
+    /// let states: Vec<_> = transactions.into_iter().map(|execute| execute()).collect();
+    /// assert_eq!(
+    ///     vec![TransactionState::Ok(1), TransactionState::Rollback((), Ok(()))],
+    ///     states
+    /// );
+    /// ```
+    fn into_fn(self) -> impl FnOnce() -> StateOf<Self> {
+        move || self.execute()
+    }
+
+    /// Like [`Self::execute`], but if the transaction succeeded, additionally returns a
+    /// compensation that runs [`Self::rollback`] later on.
+    ///
+    /// This allows undoing a successful transaction later, for example in a saga where a later
+    /// step fails. The compensation is only returned if the state is [`TransactionState::Ok`] or
+    /// [`TransactionState::OkButFailedFinally`]. It takes ownership of the transaction, so the
+    /// transaction and everything it holds stay alive until the compensation is called or
+    /// dropped. Since [`Self::rollback`] expects the error that caused the rollback, the
+    /// compensation must be called with an error describing why the transaction is undone.
+    ///
+    /// `finally` is not run again by the compensation.
+    ///
+    /// ```rust
+    /// use transaction_rollback::{Transaction, TransactionState};
+    /// # type State = TransactionState<(), (), &'static str, (), (), ()>;
+    /// struct Reserve<'a> { seats: &'a mut u32 }
+    ///
+    /// impl Transaction for Reserve<'_> {
+    ///     type Error = &'static str;
+    ///     // ...
+    /// #     type BeforeError = ();
+    /// #     type Ok = ();
+    /// #     type RollbackOk = ();
+    /// #     type RollbackError = ();
+    /// #     type FinallyError = ();
+    /// #     fn before(&mut self) -> Result<(), ()> { Ok(()) }
+    ///     fn operation(&mut self) -> Result<(), &'static str> {
+    ///         *self.seats -= 1;
+    ///         Ok(())
+    ///     }
+    ///
+    ///     fn rollback(&mut self, _err: &&'static str) -> Result<(), ()> {
+    ///         *self.seats += 1;
+    ///         Ok(())
+    ///     }
+    /// #     fn finally(&mut self, _: &State) -> Result<(), ()> { Ok(()) }
+    /// }
+    ///
+    /// let mut seats = 10;
+    /// let (state, compensation) = Reserve { seats: &mut seats }.execute_with_compensation();
+    /// assert_eq!(TransactionState::Ok(()), state);
+    ///
+    /// // A later step failed, so undo the reservation.
+    /// compensation.unwrap()(&"payment failed").unwrap();
+    /// assert_eq!(10, seats);
+    /// ```
+    #[allow(clippy::type_complexity)]
+    fn execute_with_compensation(
+        mut self,
+    ) -> (
+        StateOf<Self>,
+        Option<impl FnOnce(&Self::Error) -> Result<Self::RollbackOk, Self::RollbackError>>,
+    ) {
+        let state = Borrowed(&mut self).execute();
+        let succeeded = matches!(
+            state,
+            TransactionState::Ok(_) | TransactionState::OkButFailedFinally(_, _)
+        );
+        let compensation = succeeded.then_some(move |err: &Self::Error| {
+            let mut transaction = self;
+            transaction.rollback(err)
+        });
+        (state, compensation)
+    }
+
+    /// Returns a function that runs [`Self::rollback`] of a clone of this transaction.
+    ///
+    /// This allows reusing the compensation logic of the transaction elsewhere, for example in
+    /// a [`crate::RollbackGuard`] for an operation that is not a transaction. Since
+    /// [`Self::rollback`] takes `&mut self`, but the returned function can be called any number
+    /// of times, each call rolls back a fresh clone of the transaction as it was when the
+    /// template was created. This imposes some constraints on the `self`-access of the rollback:
+    ///
+    /// - It sees the state of the transaction at the time the template was created, not any
+    ///   changes made afterwards, for example by executing the transaction.
+    /// - Changes it makes to the transaction are discarded after each call. Only changes to data
+    ///   shared between clones, like data behind references or `Rc`s, are kept.
+    ///
+    /// ```rust
+    /// # use std::cell::Cell;
+    /// use transaction_rollback::{rollback, Rollback, Transaction, TransactionState};
+    /// # type State = TransactionState<(), (), &'static str, (), (), ()>;
+    /// #[derive(Clone)]
+    /// struct Reserve<'a> { seats: &'a Cell<u32> }
+    ///
+    /// impl Transaction for Reserve<'_> {
+    ///     type Error = &'static str;
+    ///     // ...
+    /// #     type BeforeError = ();
+    /// #     type Ok = ();
+    /// #     type RollbackOk = ();
+    /// #     type RollbackError = ();
+    /// #     type FinallyError = ();
+    /// #     fn before(&mut self) -> Result<(), ()> { Ok(()) }
+    /// #     fn operation(&mut self) -> Result<(), &'static str> { Ok(()) }
+    ///     fn rollback(&mut self, _err: &&'static str) -> Result<(), ()> {
+    ///         self.seats.set(self.seats.get() + 1);
+    ///         Ok(())
+    ///     }
+    /// #     fn finally(&mut self, _: &State) -> Result<(), ()> { Ok(()) }
+    /// }
+    ///
+    /// let seats = Cell::new(9);
+    /// let release_seat = Reserve { seats: &seats }.rollback_template();
+    /// // Reuse the compensation for a seat reserved without the transaction.
+    /// let guard = rollback(|| release_seat(&"booking cancelled"));
+    /// guard.do_rollback().unwrap();
+    /// assert_eq!(10, seats.get());
+    /// ```
+    fn rollback_template(
+        &self,
+    ) -> impl Fn(&Self::Error) -> Result<Self::RollbackOk, Self::RollbackError> + use<Self>
+    where
+        Self: Clone,
+    {
+        let template = self.clone();
+        move |err| template.clone().rollback(err)
+    }
+
+    /// Like [`Self::execute`], but bundles the resulting state with [`Self::context`].
+    ///
+    /// The context is captured before the transaction is executed, since executing consumes
+    /// it. It thus reflects the transaction as it was constructed, for example its inputs.
+    ///
+    /// ```rust
+    /// use transaction_rollback::{Transaction, TransactionState};
+    /// # type State = TransactionState<(), (), (), (), (), ()>;
+    /// struct Upload { request_id: u32 }
+    ///
+    /// impl Transaction for Upload {
+    ///     // ...
+    /// #     type BeforeError = ();
+    /// #     type Ok = ();
+    /// #     type Error = ();
+    /// #     type RollbackOk = ();
+    /// #     type RollbackError = ();
+    /// #     type FinallyError = ();
+    ///     fn context(&self) -> Option<String> {
+    ///         Some(format!("request {}", self.request_id))
+    ///     }
+    ///
+    /// #     fn before(&mut self) -> Result<(), ()> { Ok(()) }
+    /// #     fn operation(&mut self) -> Result<(), ()> { Err(()) }
+    /// #     fn rollback(&mut self, _: &()) -> Result<(), ()> { Ok(()) }
+    /// #     fn finally(&mut self, _: &State) -> Result<(), ()> { Ok(()) }
+    ///     // ...
+    /// }
+    ///
+    /// let result = Upload { request_id: 42 }.execute_with_context();
+    /// assert_eq!(TransactionState::Rollback((), Ok(())), result.state);
+    /// assert_eq!(Some("request 42".to_string()), result.context);
+    /// ```
+    fn execute_with_context(self) -> ContextualState<StateOf<Self>> {
+        let context = self.context();
+        ContextualState {
+            state: self.execute(),
+            context,
+        }
+    }
+
+    /// Like [`Self::execute`], but additionally returns a clone of the transaction taken before
+    /// it was executed.
+    ///
+    /// The clone is taken before [`Self::before`] or any other phase runs, so it reflects the
+    /// inputs of the transaction before execution mutated anything. This allows replaying a
+    /// failed transaction later with identical inputs, for example for debugging. This requires
+    /// the transaction to implement [`Clone`]; note that cloning shares everything the
+    /// transaction only references, like `Rc`s or borrowed data, with the executed transaction.
+    ///
+    /// ```rust
+    /// use transaction_rollback::{Transaction, TransactionState};
+    /// # type State = TransactionState<(), (), (), (), (), ()>;
+    /// #[derive(Clone)]
+    /// struct Upload { attempts: u32 }
+    ///
+    /// impl Transaction for Upload {
+    ///     // ...
+    /// #     type BeforeError = ();
+    /// #     type Ok = ();
+    /// #     type Error = ();
+    /// #     type RollbackOk = ();
+    /// #     type RollbackError = ();
+    /// #     type FinallyError = ();
+    ///     fn before(&mut self) -> Result<(), ()> {
+    ///         self.attempts += 1;
+    ///         Ok(())
+    ///     }
+    ///
+    /// #     fn operation(&mut self) -> Result<(), ()> { Err(()) }
+    /// #     fn rollback(&mut self, _: &()) -> Result<(), ()> { Ok(()) }
+    /// #     fn finally(&mut self, _: &State) -> Result<(), ()> { Ok(()) }
+    ///     // ...
+    /// }
+    ///
+    /// let (state, captured) = Upload { attempts: 0 }.execute_capturing();
+    /// assert_eq!(TransactionState::Rollback((), Ok(())), state);
+    /// assert_eq!(0, captured.attempts);
+    /// // Replay with the same inputs.
+    /// let (state, _) = captured.execute_capturing();
+    /// assert_eq!(TransactionState::Rollback((), Ok(())), state);
+    /// ```
+    fn execute_capturing(self) -> (StateOf<Self>, Self)
+    where
+        Self: Clone,
+    {
+        let captured = self.clone();
+        (self.execute(), captured)
+    }
+
+    /// Like [`Self::execute`], but additionally runs `finally` after [`Self::finally`].
+    ///
+    /// This is useful if the finalization depends on context that is only available when the
+    /// transaction is executed. Both are run, even if [`Self::finally`] fails. If either fails,
+    /// the state is the corresponding `...ButFailedFinally` variant. If both fail, the error of
+    /// [`Self::finally`] is used and the error of the injected `finally` is discarded.
+    ///
+    /// ```rust
+    /// # use std::cell::RefCell;
+    /// use transaction_rollback::{simple_transaction, Transaction, TransactionState};
+    ///
+    /// let log = RefCell::new(Vec::new());
+    /// let state = simple_transaction::<_, _, _, (), ()>(|| Ok(()), |_| Ok(()))
+    ///     .execute_with_finally(|state| {
+    ///         log.borrow_mut().push(format!("finished with {state:?}"));
+    ///         Ok(())
+    ///     });
+    /// assert_eq!(TransactionState::Ok(()), state);
+    /// assert_eq!(vec!["finished with Ok(())"], *log.borrow());
+    /// ```
+    fn execute_with_finally<F>(self, finally: F) -> StateOf<Self>
+    where
+        F: FnMut(&StateOf<Self>) -> Result<(), Self::FinallyError>,
+    {
+        InjectedFinally {
+            transaction: self,
+            finally,
+            replace: false,
+        }
+        .execute()
+    }
+
+    /// Like [`Self::execute`], but runs `finally` instead of [`Self::finally`].
+    ///
+    /// See [`Self::execute_with_finally`] to run both.
+    fn execute_replacing_finally<F>(self, finally: F) -> StateOf<Self>
+    where
+        F: FnMut(&StateOf<Self>) -> Result<(), Self::FinallyError>,
+    {
+        InjectedFinally {
+            transaction: self,
+            finally,
+            replace: true,
+        }
+        .execute()
+    }
+
+    /// Like [`Self::execute`], but catches a panic in [`Self::finally`] and turns it into an
+    /// error of `finally`.
+    ///
+    /// A panic in `finally` is especially costly, since it happens after the operation
+    /// already succeeded or was rolled back, and loses that result. With this, the state
+    /// before `finally` is kept and returned as the corresponding `...ButFailedFinally`
+    /// variant, with the [`PanicError`] converted into the error of `finally`. Panics in all
+    /// other phases are not caught, for this use [`UnwindCheckedTransaction`].
+    ///
+    /// This requires `Self::FinallyError: From<PanicError>`. Unlike for
+    /// [`UnwindCheckedTransaction`], the transaction does not need to be [`UnwindSafe`]: it is
+    /// consumed and not used after `finally`, so a state left inconsistent by the panic can not
+    /// be observed.
+    ///
+    /// ```rust
+    /// use transaction_rollback::{PanicError, Transaction, TransactionState};
+    /// # type State = TransactionState<(), u32, (), (), (), FinallyError>;
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// struct FinallyError;
+    ///
+    /// impl From<PanicError> for FinallyError {
+    ///     fn from(_: PanicError) -> Self {
+    ///         FinallyError
+    ///     }
+    /// }
+    ///
+    /// struct Upload;
+    ///
+    /// impl Transaction for Upload {
+    ///     type Ok = u32;
+    ///     type FinallyError = FinallyError;
+    ///     // ...
+    /// #     type BeforeError = ();
+    /// #     type Error = ();
+    /// #     type RollbackOk = ();
+    /// #     type RollbackError = ();
+    /// #     fn before(&mut self) -> Result<(), ()> { Ok(()) }
+    ///
+    ///     fn operation(&mut self) -> Result<u32, ()> {
+    ///         Ok(42)
+    ///     }
+    ///
+    /// #     fn rollback(&mut self, _: &()) -> Result<(), ()> { Ok(()) }
+    ///     fn finally(&mut self, _: &State) -> Result<(), FinallyError> {
+    ///         panic!("oh no")
+    ///     }
+    /// }
+    ///
+    /// assert_eq!(
+    ///     TransactionState::OkButFailedFinally(42, FinallyError),
+    ///     Upload.execute_protect_finally()
+    /// );
+    /// ```
+    fn execute_protect_finally(self) -> StateOf<Self>
+    where
+        Self::FinallyError: From<PanicError>,
+    {
+        ProtectedFinally(self).execute()
+    }
+
+    /// Like [`Self::execute`], but retries a failed [`Self::rollback`] up to `attempts` times.
+    ///
+    /// Only the rollback is retried, the operation is not run again. This targets flaky
+    /// compensation logic, for example a cleanup over an unreliable network. Each retry is
+    /// passed the original error of the operation, which is also the error kept in the
+    /// resulting state. The state contains the result of the last rollback attempt, so it is
+    /// [`TransactionState::Rollback`] with `Ok` if any attempt succeeded. With `0` attempts,
+    /// this is the same as [`Self::execute`].
+    ///
+    /// ```rust
+    /// use transaction_rollback::{Transaction, TransactionState};
+    /// # type State = TransactionState<(), (), &'static str, (), u32, ()>;
+    /// struct Upload { failing_cleanups: u32 }
+    ///
+    /// impl Transaction for Upload {
+    ///     // ...
+    /// #     type BeforeError = ();
+    /// #     type Ok = ();
+    /// #     type Error = &'static str;
+    /// #     type RollbackOk = ();
+    /// #     type RollbackError = u32;
+    /// #     type FinallyError = ();
+    /// #     fn before(&mut self) -> Result<(), ()> { Ok(()) }
+    ///     fn operation(&mut self) -> Result<(), &'static str> {
+    ///         Err("upload failed")
+    ///     }
+    ///
+    ///     fn rollback(&mut self, _err: &&'static str) -> Result<(), u32> {
+    ///         match self.failing_cleanups {
+    ///             0 => Ok(()),
+    ///             n => {
+    ///                 self.failing_cleanups -= 1;
+    ///                 Err(n)
+    ///             }
+    ///         }
+    ///     }
+    /// #     fn finally(&mut self, _: &State) -> Result<(), ()> { Ok(()) }
+    /// }
+    ///
+    /// assert_eq!(
+    ///     TransactionState::Rollback("upload failed", Ok(())),
+    ///     Upload { failing_cleanups: 2 }.execute_retry_rollback(2)
+    /// );
+    /// assert_eq!(
+    ///     TransactionState::Rollback("upload failed", Err(1)),
+    ///     Upload { failing_cleanups: 3 }.execute_retry_rollback(2)
+    /// );
+    /// ```
+    fn execute_retry_rollback(self, attempts: usize) -> StateOf<Self> {
+        RetriedRollback {
+            transaction: self,
+            attempts,
+        }
+        .execute()
+    }
+
+    /// Like [`Self::execute`], but first checks [`Self::precondition`].
+    ///
+    /// If the precondition is not met, nothing else is run and [`TransactionState::Skipped`]
+    /// is returned with the reason. Otherwise this is identical to [`Self::execute`].
+    ///
+    /// ```rust
+    /// use transaction_rollback::{Transaction, TransactionState};
+    /// # type State = TransactionState<&'static str, (), (), (), (), ()>;
+    /// struct CreateDir { exists: bool }
+    ///
+    /// impl Transaction for CreateDir {
+    ///     type BeforeError = &'static str;
+    ///     // ...
+    /// #     type Ok = ();
+    /// #     type Error = ();
+    /// #     type RollbackOk = ();
+    /// #     type RollbackError = ();
+    /// #     type FinallyError = ();
+    ///
+    ///     fn precondition(&mut self) -> Result<(), &'static str> {
+    ///         if self.exists { Err("already exists") } else { Ok(()) }
+    ///     }
+    ///
+    /// #     fn before(&mut self) -> Result<(), &'static str> { Ok(()) }
+    /// #     fn operation(&mut self) -> Result<(), ()> { Ok(()) }
+    /// #     fn rollback(&mut self, _: &()) -> Result<(), ()> { Ok(()) }
+    /// #     fn finally(&mut self, _: &State) -> Result<(), ()> { Ok(()) }
+    ///     // ...
+    /// }
+    ///
+    /// let state = CreateDir { exists: true }.execute_with_precondition();
+    /// assert!(state.is_skipped());
+    /// assert_eq!(Some(&"already exists"), state.skip_reason());
+    /// assert_eq!(TransactionState::Ok(()), CreateDir { exists: false }.execute_with_precondition());
+    /// ```
+    #[allow(clippy::type_complexity)]
+    fn execute_with_precondition(
+        mut self,
+    ) -> TransactionState<
+        Self::BeforeError,
+        Self::Ok,
+        Self::Error,
+        Self::RollbackOk,
+        Self::RollbackError,
+        Self::FinallyError,
+    > {
+        match self.precondition() {
+            Ok(()) => self.execute(),
+            Err(reason) => TransactionState::Skipped(reason),
+        }
+    }
+
+    /// Like [`Self::execute`], but an unwind (`panic`) in any of the steps of the transaction
+    /// is caught and turned into an error by `to_error`.
+    ///
+    /// This is an alternative to [`UnwindCheckedTransaction::execute_unwind_checked`] that does
+    /// not require the error types to implement [`From<PanicError>`].
+    ///
+    /// `to_error` is called with the phase that panicked and must return the [`PhaseError`]
+    /// variant for that phase, which is then used as the error of that phase:
+    ///
+    /// - [`TransactionPhase::Before`] to [`PhaseError::Before`]
+    /// - [`TransactionPhase::Operation`] and [`TransactionPhase::Validate`] to
+    ///   [`PhaseError::Operation`]
+    /// - [`TransactionPhase::Rollback`] to [`PhaseError::Rollback`]
+    /// - [`TransactionPhase::Finally`] to [`PhaseError::Finally`]
+    ///
+    /// If [`Self::should_rollback`] panics, the transaction is rolled back. If
+    /// [`Self::skip_finally`] panics, `finally` is not run and the panic is turned into the error
+    /// of the [`TransactionPhase::Finally`] phase.
+    ///
+    /// ```rust
+    /// use transaction_rollback::{
+    ///     PhaseError, Transaction, TransactionPhase, TransactionState,
+    /// };
+    /// # type State = TransactionState<(), (), String, (), (), ()>;
+    /// struct Panicking;
+    ///
+    /// impl Transaction for Panicking {
+    ///     type Error = String;
+    ///     // ...
+    /// #     type BeforeError = ();
+    /// #     type Ok = ();
+    /// #     type RollbackOk = ();
+    /// #     type RollbackError = ();
+    /// #     type FinallyError = ();
+    ///
+    ///     fn operation(&mut self) -> Result<(), String> {
+    ///         panic!("oh no")
+    ///     }
+    ///
+    /// #     fn before(&mut self) -> Result<(), ()> { Ok(()) }
+    /// #     fn rollback(&mut self, _: &String) -> Result<(), ()> { Ok(()) }
+    /// #     fn finally(&mut self, _: &State) -> Result<(), ()> { Ok(()) }
+    ///     // ...
+    /// }
+    ///
+    /// # std::panic::set_hook(Box::new(|_| {}));
+    /// let state = Panicking.execute_unwind_checked_map(|phase, _panic| match phase {
+    ///     TransactionPhase::Before => PhaseError::Before(()),
+    ///     TransactionPhase::Operation | TransactionPhase::Validate => {
+    ///         PhaseError::Operation(format!("{phase:?} panicked"))
+    ///     }
+    ///     TransactionPhase::Rollback => PhaseError::Rollback(()),
+    ///     TransactionPhase::Finally => PhaseError::Finally(()),
+    /// });
+    /// assert_eq!(
+    ///     TransactionState::Rollback("Operation panicked".to_string(), Ok(())),
+    ///     state
+    /// );
+    /// ```
+    ///
+    /// # Panics
+    /// If `to_error` returns a variant that does not belong to the phase that panicked, or
+    /// if executing this transaction would exceed the maximum depth of nested transactions,
+    /// see [`crate::set_max_transaction_depth`].
+    #[allow(clippy::type_complexity)]
+    fn execute_unwind_checked_map<M>(self, to_error: M) -> StateOf<Self>
+    where
+        Self: UnwindSafe,
+        M: Fn(
+            TransactionPhase,
+            PanicError,
+        ) -> PhaseError<
+            Self::BeforeError,
+            Self::Error,
+            Self::RollbackError,
+            Self::FinallyError,
+        >,
+    {
+        _execute_unwind_checked(self, to_error, true)
+    }
+
+    /// Checks whether the transaction should run at all. This is only called by
+    /// [`Self::execute_with_precondition`], and if it fails, the transaction is skipped.
+    ///
+    /// By default the precondition is always met.
+    fn precondition(&mut self) -> Result<(), Self::BeforeError> {
+        Ok(())
+    }
+
+    /// Checks whether the transaction would likely succeed, without running it. This is never
+    /// called by any of the `execute` methods, but intended for pre-flight validation, for
+    /// example in a UI.
+    ///
+    /// Implementations must not have side effects; side-effecting setup belongs into
+    /// [`Self::before`]. By default this always succeeds.
+    fn check(&self) -> Result<(), Self::BeforeError> {
+        Ok(())
+    }
+
+    /// Diagnostic context of the transaction, for example a request ID or its inputs. This is
+    /// only used by [`Self::execute_with_context`].
+    ///
+    /// By default there is no context.
+    fn context(&self) -> Option<String> {
+        None
+    }
+
     /// Performs operations to prepare the transaction. If this fails, no rollback is run.
     /// If it succeeds, the transaction can continue.
     fn before(&mut self) -> Result<(), Self::BeforeError>;
@@ -93,6 +740,164 @@ pub trait Transaction: Sized {
     /// If it succeeds, the transaction can continue.
     fn operation(&mut self) -> Result<Self::Ok, Self::Error>;
 
+    /// Checks the result of a successful [`Self::operation`], before [`Self::finally`] is run.
+    ///
+    /// If this returns an error, the operation is treated as failed: [`Self::rollback`] is run
+    /// with the returned error and the `Ok` value is discarded. This is useful if the
+    /// operation technically succeeded, but produced a result that violates some
+    /// post-condition.
+    ///
+    /// This is also the place for a commit step that should only run on success and can veto
+    /// it, unlike [`Self::finally`], which always runs. To tell a failed commit apart from a
+    /// failed operation in the resulting [`TransactionState::Rollback`], return a dedicated
+    /// variant of [`Self::Error`].
+    ///
+    /// This is not called if the operation failed. By default this always succeeds.
+    ///
+    /// ```rust
+    /// use transaction_rollback::{Transaction, TransactionState};
+    /// # type State = TransactionState<(), u8, &'static str, (), (), ()>;
+    /// struct Increment(u8);
+    ///
+    /// impl Transaction for Increment {
+    ///     // ...
+    /// #     type BeforeError = ();
+    /// #     type Ok = u8;
+    /// #     type Error = &'static str;
+    /// #     type RollbackOk = ();
+    /// #     type RollbackError = ();
+    /// #     type FinallyError = ();
+    /// #     fn before(&mut self) -> Result<(), ()> { Ok(()) }
+    ///     fn operation(&mut self) -> Result<u8, &'static str> {
+    ///         self.0 = self.0.wrapping_add(1);
+    ///         Ok(self.0)
+    ///     }
+    ///
+    ///     fn validate(&mut self, ok: &u8) -> Result<(), &'static str> {
+    ///         if *ok == 0 { Err("overflowed") } else { Ok(()) }
+    ///     }
+    ///
+    ///     fn rollback(&mut self, _err: &&'static str) -> Result<(), ()> {
+    ///         self.0 = self.0.wrapping_sub(1);
+    ///         Ok(())
+    ///     }
+    /// #     fn finally(&mut self, _: &State) -> Result<(), ()> { Ok(()) }
+    /// }
+    ///
+    /// assert_eq!(TransactionState::Ok(2), Increment(1).execute());
+    /// assert_eq!(TransactionState::Rollback("overflowed", Ok(())), Increment(255).execute());
+    /// ```
+    fn validate(&mut self, _ok: &Self::Ok) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Decides whether a failed operation should be rolled back.
+    ///
+    /// If this returns `false` for the error of [`Self::operation`] (or [`Self::validate`]),
+    /// [`Self::rollback`] is not called and the state is [`TransactionState::FailedNoRollback`].
+    /// This is useful if only some errors require compensation.
+    ///
+    /// By default every error is rolled back.
+    ///
+    /// ```rust
+    /// use transaction_rollback::{Transaction, TransactionState};
+    /// # type State = TransactionState<(), (), std::io::ErrorKind, (), (), ()>;
+    /// use std::io::ErrorKind;
+    ///
+    /// struct Upload(ErrorKind);
+    ///
+    /// impl Transaction for Upload {
+    ///     type Error = ErrorKind;
+    ///     // ...
+    /// #     type BeforeError = ();
+    /// #     type Ok = ();
+    /// #     type RollbackOk = ();
+    /// #     type RollbackError = ();
+    /// #     type FinallyError = ();
+    /// #     fn before(&mut self) -> Result<(), ()> { Ok(()) }
+    ///
+    ///     fn operation(&mut self) -> Result<(), ErrorKind> {
+    ///         Err(self.0)
+    ///     }
+    ///
+    ///     fn should_rollback(&self, err: &ErrorKind) -> bool {
+    ///         // The upload was already complete, only the confirmation timed out.
+    ///         *err != ErrorKind::TimedOut
+    ///     }
+    ///
+    /// #     fn rollback(&mut self, _: &ErrorKind) -> Result<(), ()> { Ok(()) }
+    /// #     fn finally(&mut self, _: &State) -> Result<(), ()> { Ok(()) }
+    ///     // ...
+    /// }
+    ///
+    /// assert_eq!(
+    ///     TransactionState::FailedNoRollback(ErrorKind::TimedOut),
+    ///     Upload(ErrorKind::TimedOut).execute()
+    /// );
+    /// assert_eq!(
+    ///     TransactionState::Rollback(ErrorKind::BrokenPipe, Ok(())),
+    ///     Upload(ErrorKind::BrokenPipe).execute()
+    /// );
+    /// ```
+    fn should_rollback(&self, _err: &Self::Error) -> bool {
+        true
+    }
+
+    /// Decides whether [`Self::finally`] should be skipped for the given state.
+    ///
+    /// This is called after the operation and, if needed, the rollback ran. If it returns
+    /// `true`, `finally` is not run and the state is returned unchanged. This means
+    /// [`TransactionState::OkButFailedFinally`], [`TransactionState::RollbackButFailedFinally`]
+    /// and [`TransactionState::FailedNoRollbackButFailedFinally`] can not occur for a skipped
+    /// `finally`. A `finally` injected via [`Self::execute_with_finally`] or
+    /// [`Self::execute_replacing_finally`] is skipped as well.
+    ///
+    /// By default `finally` is never skipped.
+    ///
+    /// ```rust
+    /// use transaction_rollback::{StateOf, Transaction, TransactionState};
+    ///
+    /// struct Download {
+    ///     cached: bool,
+    ///     cleaned_up: bool,
+    /// }
+    ///
+    /// impl Transaction for Download {
+    ///     type FinallyError = &'static str;
+    ///     // ...
+    /// #     type BeforeError = ();
+    /// #     type Ok = ();
+    /// #     type Error = ();
+    /// #     type RollbackOk = ();
+    /// #     type RollbackError = ();
+    /// #     fn before(&mut self) -> Result<(), ()> { Ok(()) }
+    /// #     fn operation(&mut self) -> Result<(), ()> { Ok(()) }
+    /// #     fn rollback(&mut self, _: &()) -> Result<(), ()> { Ok(()) }
+    ///
+    ///     fn skip_finally(&self, _state: &StateOf<Self>) -> bool {
+    ///         // Nothing was downloaded into the temporary directory.
+    ///         self.cached
+    ///     }
+    ///
+    ///     fn finally(&mut self, _state: &StateOf<Self>) -> Result<(), &'static str> {
+    ///         self.cleaned_up = true;
+    ///         Err("can not remove temporary directory")
+    ///     }
+    /// }
+    ///
+    /// let cached = Download { cached: true, cleaned_up: false };
+    /// assert_eq!(TransactionState::Ok(()), cached.execute());
+    ///
+    /// let fresh = Download { cached: false, cleaned_up: false };
+    /// assert_eq!(
+    ///     TransactionState::OkButFailedFinally((), "can not remove temporary directory"),
+    ///     fresh.execute()
+    /// );
+    /// ```
+    fn skip_finally(&self, _state: &StateOf<Self>) -> bool {
+        false
+    }
+
     /// Performs a rollback if the operation failed.
     fn rollback(
         &mut self,
@@ -102,8 +907,88 @@ pub trait Transaction: Sized {
     /// Performs an action to always perform at the end of the operation, no matter if it had
     /// to be rolled back or not.
     ///
-    /// The passed in `state` can be expected to be either `TransactionState::Ok` or
-    /// `TransactionState::Rollback`.
+    /// The passed in `state` can be expected to be either `TransactionState::Ok`,
+    /// `TransactionState::Rollback` or `TransactionState::FailedNoRollback`.
+    ///
+    /// By default, this calls [`Self::finally_on_success`] for `Ok` and
+    /// [`Self::finally_on_rollback`] for `Rollback`, and does nothing for `FailedNoRollback`.
+    /// Implementing this method replaces that dispatch, so the two branch methods are then only
+    /// called if the implementation calls them itself.
+    ///
+    /// If this fails, the state is turned into the corresponding `...ButFailedFinally` variant:
+    ///
+    /// ```rust
+    /// use transaction_rollback::{
+    ///     PanicError, StateOf, Transaction, TransactionState, UnwindCheckedTransaction,
+    /// };
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// enum Error {
+    ///     Operation,
+    ///     Finally,
+    ///     Panic,
+    /// }
+    ///
+    /// impl From<PanicError> for Error {
+    ///     fn from(_: PanicError) -> Self {
+    ///         Error::Panic
+    ///     }
+    /// }
+    ///
+    /// #[derive(Clone, Copy)]
+    /// enum Outcome {
+    ///     Ok,
+    ///     Rollback,
+    ///     NoRollback,
+    /// }
+    ///
+    /// impl Transaction for Outcome {
+    ///     type BeforeError = Error;
+    ///     type Ok = ();
+    ///     type Error = Error;
+    ///     type RollbackOk = ();
+    ///     type RollbackError = Error;
+    ///     type FinallyError = Error;
+    ///
+    ///     fn before(&mut self) -> Result<(), Error> {
+    ///         Ok(())
+    ///     }
+    ///
+    ///     fn operation(&mut self) -> Result<(), Error> {
+    ///         match self {
+    ///             Outcome::Ok => Ok(()),
+    ///             _ => Err(Error::Operation),
+    ///         }
+    ///     }
+    ///
+    ///     fn should_rollback(&self, _: &Error) -> bool {
+    ///         !matches!(self, Outcome::NoRollback)
+    ///     }
+    ///
+    ///     fn rollback(&mut self, _: &Error) -> Result<(), Error> {
+    ///         Ok(())
+    ///     }
+    ///
+    ///     fn finally(&mut self, _: &StateOf<Self>) -> Result<(), Error> {
+    ///         Err(Error::Finally)
+    ///     }
+    /// }
+    ///
+    /// for execute in [Outcome::execute, Outcome::execute_unwind_checked] {
+    ///     assert_eq!(
+    ///         TransactionState::OkButFailedFinally((), Error::Finally),
+    ///         execute(Outcome::Ok)
+    ///     );
+    ///     assert_eq!(
+    ///         TransactionState::RollbackButFailedFinally(Error::Operation, Ok(()), Error::Finally),
+    ///         execute(Outcome::Rollback)
+    ///     );
+    ///     assert_eq!(
+    ///         TransactionState::FailedNoRollbackButFailedFinally(Error::Operation, Error::Finally),
+    ///         execute(Outcome::NoRollback)
+    ///     );
+    /// }
+    /// ```
     #[allow(clippy::type_complexity)]
     fn finally(
         &mut self,
@@ -115,7 +1000,147 @@ pub trait Transaction: Sized {
             Self::RollbackError,
             Self::FinallyError,
         >,
-    ) -> Result<(), Self::FinallyError>;
+    ) -> Result<(), Self::FinallyError> {
+        match state {
+            TransactionState::Ok(ok) => self.finally_on_success(ok),
+            TransactionState::Rollback(err, rollback_result) => {
+                self.finally_on_rollback(err, rollback_result)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The `finally` of a successful transaction, called by the default [`Self::finally`] with
+    /// the `Ok` value of the operation. Does nothing by default.
+    fn finally_on_success(&mut self, _ok: &Self::Ok) -> Result<(), Self::FinallyError> {
+        Ok(())
+    }
+
+    /// The `finally` of a rolled back transaction, called by the default [`Self::finally`] with
+    /// the error of the operation and the result of the rollback. Does nothing by default.
+    ///
+    /// Together with [`Self::finally_on_success`], this allows implementing only the branch of
+    /// `finally` that is needed, without matching on the state:
+    ///
+    /// ```rust
+    /// # use std::cell::RefCell;
+    /// use transaction_rollback::{Transaction, TransactionState};
+    ///
+    /// struct Upload<'a> { alerts: &'a RefCell<Vec<String>> }
+    ///
+    /// impl Transaction for Upload<'_> {
+    ///     // ...
+    /// #     type BeforeError = ();
+    /// #     type Ok = ();
+    /// #     type Error = &'static str;
+    /// #     type RollbackOk = ();
+    /// #     type RollbackError = ();
+    /// #     type FinallyError = ();
+    /// #     fn before(&mut self) -> Result<(), ()> { Ok(()) }
+    /// #     fn operation(&mut self) -> Result<(), &'static str> { Err("disk full") }
+    /// #     fn rollback(&mut self, _: &&'static str) -> Result<(), ()> { Ok(()) }
+    ///     fn finally_on_rollback(
+    ///         &mut self,
+    ///         err: &&'static str,
+    ///         _rollback_result: &Result<(), ()>,
+    ///     ) -> Result<(), ()> {
+    ///         self.alerts.borrow_mut().push(format!("upload rolled back: {err}"));
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let alerts = RefCell::new(Vec::new());
+    /// let state = Upload { alerts: &alerts }.execute();
+    /// assert_eq!(TransactionState::Rollback("disk full", Ok(())), state);
+    /// assert_eq!(vec!["upload rolled back: disk full"], *alerts.borrow());
+    /// ```
+    fn finally_on_rollback(
+        &mut self,
+        _err: &Self::Error,
+        _rollback_result: &Result<Self::RollbackOk, Self::RollbackError>,
+    ) -> Result<(), Self::FinallyError> {
+        Ok(())
+    }
+}
+
+/// A transaction with a `finally` injected at execution time, see
+/// [`Transaction::execute_with_finally`] and [`Transaction::execute_replacing_finally`].
+struct InjectedFinally<T, F> {
+    transaction: T,
+    finally: F,
+    // Whether `finally` replaces the `finally` of `transaction` or runs after it.
+    replace: bool,
+}
+
+impl<T, F> Transaction for InjectedFinally<T, F>
+where
+    T: Transaction,
+    F: FnMut(&StateOf<T>) -> Result<(), T::FinallyError>,
+{
+    forward_transaction! { T, transaction;
+        fn finally(&mut self, state: &StateOf<T>) -> Result<(), Self::FinallyError> {
+            if self.replace {
+                (self.finally)(state)
+            } else {
+                let result = self.transaction.finally(state);
+                let injected = (self.finally)(state);
+                result.and(injected)
+            }
+        }
+    }
+}
+
+/// A transaction executed by reference, so that it can still be used afterwards, see
+/// [`Transaction::execute_with_compensation`].
+struct Borrowed<'a, T>(&'a mut T);
+
+impl<T> Transaction for Borrowed<'_, T>
+where
+    T: Transaction,
+{
+    forward_transaction! { T, 0; }
+}
+
+/// A transaction whose `finally` is protected against panics, see
+/// [`Transaction::execute_protect_finally`].
+struct ProtectedFinally<T>(T);
+
+impl<T> Transaction for ProtectedFinally<T>
+where
+    T: Transaction,
+    T::FinallyError: From<PanicError>,
+{
+    forward_transaction! { T, 0;
+        fn finally(&mut self, state: &StateOf<T>) -> Result<(), Self::FinallyError> {
+            // The transaction is not used after `finally`, see `execute_protect_finally`.
+            _catch_unwind(|| self.0.finally(state), From::from)
+        }
+    }
+}
+
+/// A transaction whose failed rollback is retried, see
+/// [`Transaction::execute_retry_rollback`].
+struct RetriedRollback<T> {
+    transaction: T,
+    attempts: usize,
+}
+
+impl<T> Transaction for RetriedRollback<T>
+where
+    T: Transaction,
+{
+    forward_transaction! { T, transaction;
+        fn rollback(&mut self, err: &Self::Error) -> Result<Self::RollbackOk, Self::RollbackError> {
+            let mut result = self.transaction.rollback(err);
+            for _ in 0..self.attempts {
+                if result.is_ok() {
+                    break;
+                }
+                result = self.transaction.rollback(err);
+            }
+            result
+        }
+    }
 }
 
 /// A struct representing the value of a caught panic/unwind.
@@ -144,7 +1169,7 @@ where
     /// turned into the corresponding error type.
     #[allow(clippy::type_complexity)]
     fn execute_unwind_checked(
-        mut self,
+        self,
     ) -> TransactionState<
         Self::BeforeError,
         Self::Ok,
@@ -153,45 +1178,196 @@ where
         Self::RollbackError,
         Self::FinallyError,
     > {
-        if let Err(e) = _catch_unwind(|| self.before()) {
-            TransactionState::FailedBefore(e)
-        } else {
-            let state = match _catch_unwind(|| self.operation()) {
-                Ok(o) => TransactionState::Ok(o),
-                Err(e) => {
-                    let rollback_result = _catch_unwind(|| self.rollback(&e));
-                    TransactionState::Rollback(e, rollback_result)
-                }
-            };
-            if let Err(e) = _catch_unwind(|| self.finally(&state)) {
-                match state {
-                    TransactionState::Ok(oo) => TransactionState::OkButFailedFinally(oo, e),
-                    TransactionState::Rollback(oe, rs) => {
-                        TransactionState::RollbackButFailedFinally(oe, rs, e)
-                    }
-                    _ => unreachable!(),
+        self.execute_unwind_checked_with(true)
+    }
+
+    /// Returns a closure that executes the transaction when called, see
+    /// [`Self::execute_unwind_checked`] and [`Transaction::into_fn`].
+    fn into_fn_unwind_checked(self) -> impl FnOnce() -> StateOf<Self> {
+        move || self.execute_unwind_checked()
+    }
+
+    /// Like [`Self::execute_unwind_checked`], but if `catch_finally` is `false`, an unwind in
+    /// [`Transaction::finally`] or [`Transaction::skip_finally`] is not caught, but propagated to
+    /// the caller.
+    ///
+    /// Panics in `finally` are special, since they happen after the transaction already
+    /// succeeded or was rolled back. Turning them into an error of `finally` can hide serious
+    /// bugs, especially when the error of `finally` is not checked, because the operation
+    /// itself succeeded. Unwinds in all other steps are still caught.
+    ///
+    /// ```should_panic
+    /// use transaction_rollback::{
+    ///     PanicError, Transaction, TransactionState, UnwindCheckedTransaction,
+    /// };
+    /// # #[derive(Debug)]
+    /// # struct Error;
+    /// # impl From<PanicError> for Error { fn from(_: PanicError) -> Self { Error } }
+    /// # type State = TransactionState<Error, (), Error, (), Error, Error>;
+    /// struct PanickingFinally;
+    ///
+    /// impl Transaction for PanickingFinally {
+    ///     // ...
+    /// #     type BeforeError = Error;
+    /// #     type Ok = ();
+    /// #     type Error = Error;
+    /// #     type RollbackOk = ();
+    /// #     type RollbackError = Error;
+    /// #     type FinallyError = Error;
+    /// #     fn before(&mut self) -> Result<(), Error> { Ok(()) }
+    /// #     fn operation(&mut self) -> Result<(), Error> { Ok(()) }
+    /// #     fn rollback(&mut self, _: &Error) -> Result<(), Error> { Ok(()) }
+    ///     fn finally(&mut self, _: &State) -> Result<(), Error> {
+    ///         panic!("oh no")
+    ///     }
+    /// }
+    ///
+    /// // This panics with "oh no".
+    /// PanickingFinally.execute_unwind_checked_with(false);
+    /// ```
+    #[allow(clippy::type_complexity)]
+    fn execute_unwind_checked_with(self, catch_finally: bool) -> StateOf<Self> {
+        _execute_unwind_checked(
+            self,
+            |phase, panic| match phase {
+                TransactionPhase::Before => PhaseError::Before(panic.into()),
+                TransactionPhase::Operation | TransactionPhase::Validate => {
+                    PhaseError::Operation(panic.into())
                 }
-            } else {
-                state
+                TransactionPhase::Rollback => PhaseError::Rollback(panic.into()),
+                TransactionPhase::Finally => PhaseError::Finally(panic.into()),
+            },
+            catch_finally,
+        )
+    }
+}
+
+/// See [`Transaction::execute_unwind_checked_map`]. If `catch_finally` is `false`, panics in
+/// [`Transaction::skip_finally`] and [`Transaction::finally`] are not caught.
+#[allow(clippy::type_complexity)]
+fn _execute_unwind_checked<T, M>(mut transaction: T, to_error: M, catch_finally: bool) -> StateOf<T>
+where
+    T: Transaction + UnwindSafe,
+    M: Fn(
+        TransactionPhase,
+        PanicError,
+    ) -> PhaseError<T::BeforeError, T::Error, T::RollbackError, T::FinallyError>,
+{
+    let _depth = DepthGuard::enter();
+    let before_error = |p| match to_error(TransactionPhase::Before, p) {
+        PhaseError::Before(e) => e,
+        _ => wrong_phase(TransactionPhase::Before),
+    };
+    let operation_error = |phase, p| match to_error(phase, p) {
+        PhaseError::Operation(e) => e,
+        _ => wrong_phase(phase),
+    };
+    let rollback_error = |p| match to_error(TransactionPhase::Rollback, p) {
+        PhaseError::Rollback(e) => e,
+        _ => wrong_phase(TransactionPhase::Rollback),
+    };
+    let finally_error = |p| match to_error(TransactionPhase::Finally, p) {
+        PhaseError::Finally(e) => e,
+        _ => wrong_phase(TransactionPhase::Finally),
+    };
+
+    if let Err(e) = _catch_unwind(|| transaction.before(), before_error) {
+        TransactionState::FailedBefore(e)
+    } else {
+        let state = match _catch_unwind(
+            || transaction.operation(),
+            |p| operation_error(TransactionPhase::Operation, p),
+        )
+        .and_then(|o| {
+            _catch_unwind(
+                || transaction.validate(&o),
+                |p| operation_error(TransactionPhase::Validate, p),
+            )
+            .map(|_| o)
+        }) {
+            Ok(o) => TransactionState::Ok(o),
+            // If `should_rollback` panics, we roll back to be on the safe side.
+            Err(e)
+                if catch_unwind(AssertUnwindSafe(|| transaction.should_rollback(&e)))
+                    .unwrap_or(true) =>
+            {
+                let rollback_result = _catch_unwind(|| transaction.rollback(&e), rollback_error);
+                TransactionState::Rollback(e, rollback_result)
             }
+            Err(e) => TransactionState::FailedNoRollback(e),
+        };
+        let finally_result = if catch_finally {
+            // A panic in `skip_finally` is the error of `finally`, which then does not run.
+            _catch_unwind(|| Ok(transaction.skip_finally(&state)), finally_error).and_then(|skip| {
+                if skip {
+                    Ok(())
+                } else {
+                    _catch_unwind(|| transaction.finally(&state), finally_error)
+                }
+            })
+        } else if transaction.skip_finally(&state) {
+            Ok(())
+        } else {
+            transaction.finally(&state)
+        };
+        if let Err(e) = finally_result {
+            with_finally_error(state, e)
+        } else {
+            state
         }
     }
 }
 
-fn _catch_unwind<F, T, E>(op: F) -> Result<T, E>
+fn _catch_unwind<F, T, E, M>(op: F, on_panic: M) -> Result<T, E>
 where
     F: (FnMut() -> Result<T, E>),
-    E: From<PanicError>,
+    M: FnOnce(PanicError) -> E,
 {
     // We can assert it is UnwindSafe even though the operations may get a mutable Self,
-    // because of the requirement of the trait [`UnwindCheckedTransaction`].
+    // because of the requirement of `UnwindSafe` for the transaction.
     match catch_unwind(AssertUnwindSafe(op)) {
         Ok(Ok(v)) => Ok(v),
         Ok(Err(e)) => Err(e),
-        Err(e) => Err(PanicError(e).into()),
+        Err(e) => Err(on_panic(PanicError(e))),
+    }
+}
+
+/// Turns the state before `finally` into the corresponding `...ButFailedFinally` state.
+pub(crate) fn with_finally_error<BE, O, E, RO, RE, FE>(
+    state: TransactionState<BE, O, E, RO, RE, FE>,
+    finally_error: FE,
+) -> TransactionState<BE, O, E, RO, RE, FE> {
+    match state {
+        TransactionState::Ok(oo) => TransactionState::OkButFailedFinally(oo, finally_error),
+        TransactionState::Rollback(oe, rs) => {
+            TransactionState::RollbackButFailedFinally(oe, rs, finally_error)
+        }
+        TransactionState::FailedNoRollback(oe) => {
+            TransactionState::FailedNoRollbackButFailedFinally(oe, finally_error)
+        }
+        TransactionState::FailedBefore(_)
+        | TransactionState::OkButFailedFinally(_, _)
+        | TransactionState::RollbackButFailedFinally(_, _, _)
+        | TransactionState::Skipped(_)
+        | TransactionState::FailedNoRollbackButFailedFinally(_, _) => invalid_finally_state(),
     }
 }
 
+#[cold]
+fn invalid_finally_state() -> ! {
+    panic!(
+        "transaction-rollback: internal error: `finally` failed for a state it can not run in. \
+         This is a bug, please report it."
+    )
+}
+
+fn wrong_phase(phase: TransactionPhase) -> ! {
+    panic!(
+        "transaction-rollback: the panic of the {phase:?} phase was mapped to the error of \
+         another phase."
+    )
+}
+
 impl<T> UnwindCheckedTransaction for T
 where
     T: Transaction + UnwindSafe + RefUnwindSafe,
@@ -0,0 +1,247 @@
+use crate::rollback::private;
+use crate::{infallible_rollback, MaybeError, Rollback, RollbackGuard};
+use std::mem;
+use std::ops::{Deref, DerefMut};
+
+/// A rollback that calls `method` on `value` when the returned guard is dropped.
+///
+/// The guard owns `value` and gives access to it via [`Deref`] and [`DerefMut`]. To get the
+/// value back without running `method`, use [`MethodGuard::into_inner`]. The rollback can also
+/// be run manually via [`Rollback::do_rollback`], which returns the value afterwards.
+///
+/// ```rust
+/// use transaction_rollback::guard_method;
+///
+/// struct Connection { open: bool }
+/// impl Connection {
+///     fn close(&mut self) { self.open = false; }
+/// }
+///
+/// let conn = guard_method(Connection { open: true }, Connection::close);
+/// assert!(conn.open);
+/// // ... if something fails here, `conn` is closed on drop.
+/// let conn = conn.into_inner();
+/// assert!(conn.open);
+/// ```
+pub fn guard_method<T, F>(value: T, method: F) -> MethodGuard<T, F>
+where
+    F: FnOnce(&mut T),
+{
+    MethodGuard {
+        value: Some(value),
+        method: Some(method),
+    }
+}
+
+/// Sets `*target` to `new_value` and returns a guard that restores the previous value on
+/// rollback.
+///
+/// The previous value is moved out of `target`, so `T` does not need to implement [`Clone`].
+/// The guard borrows `target` mutably, so it can only be accessed again once the guard is gone.
+///
+/// ```rust
+/// use transaction_rollback::restore_on_rollback;
+///
+/// let mut mode = "read";
+/// let guard = restore_on_rollback(&mut mode, "write");
+/// // ... if something fails here, `mode` is restored on drop.
+/// drop(guard);
+/// assert_eq!("read", mode);
+///
+/// restore_on_rollback(&mut mode, "write").ok();
+/// assert_eq!("write", mode);
+/// ```
+pub fn restore_on_rollback<T>(target: &mut T, new_value: T) -> RollbackGuard<'_, (), ()> {
+    let old_value = mem::replace(target, new_value);
+    infallible_rollback(move || *target = old_value)
+}
+
+/// Updates `*target` in place via `update` and returns a guard that restores the previous value
+/// on rollback.
+///
+/// Unlike [`restore_on_rollback`], this captures the previous value by cloning it, which
+/// allows modifying the value in place instead of replacing it.
+///
+/// ```rust
+/// use transaction_rollback::restore_on_rollback_with;
+///
+/// let mut items = vec![1, 2];
+/// let guard = restore_on_rollback_with(&mut items, |items| items.push(3));
+/// drop(guard);
+/// assert_eq!(vec![1, 2], items);
+/// ```
+pub fn restore_on_rollback_with<T, F>(target: &mut T, update: F) -> RollbackGuard<'_, (), ()>
+where
+    T: Clone,
+    F: FnOnce(&mut T),
+{
+    let old_value = target.clone();
+    update(target);
+    infallible_rollback(move || *target = old_value)
+}
+
+/// A guard that calls a method on the value it owns when dropped.
+///
+/// To create this and for more information see [`guard_method`].
+pub struct MethodGuard<T, F>
+where
+    F: FnOnce(&mut T),
+{
+    value: Option<T>,
+    method: Option<F>,
+}
+
+impl<T, F> MethodGuard<T, F>
+where
+    F: FnOnce(&mut T),
+{
+    /// Drops the guard without calling the method and returns the value.
+    pub fn into_inner(mut self) -> T {
+        self.method.take();
+        self.value
+            .take()
+            .expect("the value is only taken when the guard is consumed")
+    }
+}
+
+impl<T, F> Deref for MethodGuard<T, F>
+where
+    F: FnOnce(&mut T),
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+            .as_ref()
+            .expect("the value is only taken when the guard is consumed")
+    }
+}
+
+impl<T, F> DerefMut for MethodGuard<T, F>
+where
+    F: FnOnce(&mut T),
+{
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+            .as_mut()
+            .expect("the value is only taken when the guard is consumed")
+    }
+}
+
+impl<T, F> Rollback for MethodGuard<T, F>
+where
+    F: FnOnce(&mut T),
+{
+    type RollbackOk = T;
+    type RollbackError = ();
+
+    /// Calls the method, consuming the guard, and returns the value.
+    fn do_rollback(mut self) -> Result<T, ()> {
+        let mut value = self.value.take().ok_or(())?;
+        if let Some(method) = self.method.take() {
+            method(&mut value);
+        }
+        Ok(value)
+    }
+}
+
+impl<T, F> Drop for MethodGuard<T, F>
+where
+    F: FnOnce(&mut T),
+{
+    fn drop(&mut self) {
+        if let (Some(value), Some(method)) = (self.value.as_mut(), self.method.take()) {
+            method(value);
+        }
+    }
+}
+
+/// A [`RollbackGuard`] bundled with a value.
+///
+/// The value is accessible via [`Deref`] and [`DerefMut`]. Dropping this drops the guard, and
+/// thus runs the rollback, as usual. [`Rollback::do_rollback`] returns the value together with
+/// the result of the rollback.
+///
+/// To create this, use [`RollbackGuard::zip`].
+///
+/// ```rust
+/// use transaction_rollback::{infallible_rollback, Rollback};
+///
+/// let guards: Vec<_> = ["a.txt", "b.txt"]
+///     .into_iter()
+///     .map(|path| infallible_rollback(move || format!("deleted {path}")).zip(path))
+///     .collect();
+///
+/// let mut results = Vec::new();
+/// for guard in guards {
+///     if *guard == "a.txt" {
+///         results.push(guard.do_rollback());
+///     } else {
+///         assert_eq!("b.txt", guard.ok());
+///     }
+/// }
+/// assert_eq!(vec![Ok(("deleted a.txt".to_string(), "a.txt"))], results);
+/// ```
+pub struct ZippedGuard<'a, T, E, V>
+where
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    pub(crate) guard: RollbackGuard<'a, T, E>,
+    pub(crate) value: V,
+}
+
+impl<'a, T, E, V> ZippedGuard<'a, T, E, V>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    /// Drops the guard without running the rollback and returns the value, see
+    /// [`RollbackGuard::ok`].
+    pub fn ok(self) -> V {
+        self.guard.ok();
+        self.value
+    }
+
+    /// Splits this into the guard and the value.
+    pub fn into_parts(self) -> (RollbackGuard<'a, T, E>, V) {
+        (self.guard, self.value)
+    }
+}
+
+impl<'a, T, E, V> Deref for ZippedGuard<'a, T, E, V>
+where
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        &self.value
+    }
+}
+
+impl<'a, T, E, V> DerefMut for ZippedGuard<'a, T, E, V>
+where
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    fn deref_mut(&mut self) -> &mut V {
+        &mut self.value
+    }
+}
+
+impl<'a, T, E, V> Rollback for ZippedGuard<'a, T, E, V>
+where
+    E: MaybeError,
+    RollbackGuard<'a, T, E>: private::DropLike,
+{
+    type RollbackOk = (T, V);
+    type RollbackError = (E, V);
+
+    /// Performs the rollback, consuming the guard, and returns its result together with the
+    /// value.
+    fn do_rollback(self) -> Result<(T, V), (E, V)> {
+        match self.guard.do_rollback() {
+            Ok(t) => Ok((t, self.value)),
+            Err(e) => Err((e, self.value)),
+        }
+    }
+}
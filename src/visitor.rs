@@ -0,0 +1,232 @@
+use crate::TransactionState;
+
+/// A visitor for [`TransactionState`], with one method per variant.
+///
+/// [`TransactionState::accept`] calls the method for the variant of the state with its values.
+/// This allows handling states the same way in multiple places by implementing the handling
+/// once. Since every method must be implemented, a variant added in the future requires
+/// updating all visitors. To handle only some variants, implement
+/// [`PartialTransactionStateVisitor`] instead.
+///
+/// ```rust
+/// use transaction_rollback::{TransactionState, TransactionStateVisitor};
+///
+/// struct ExitCode;
+///
+/// impl<BE, O, E, RO, RE, FE> TransactionStateVisitor<BE, O, E, RO, RE, FE> for ExitCode {
+///     type Output = i32;
+///
+///     fn visit_failed_before(&mut self, _: BE) -> i32 { 1 }
+///     fn visit_ok(&mut self, _: O) -> i32 { 0 }
+///     fn visit_rollback(&mut self, _: E, r: Result<RO, RE>) -> i32 {
+///         if r.is_ok() { 2 } else { 3 }
+///     }
+///     fn visit_ok_but_failed_finally(&mut self, _: O, _: FE) -> i32 { 4 }
+///     fn visit_rollback_but_failed_finally(&mut self, _: E, _: Result<RO, RE>, _: FE) -> i32 {
+///         5
+///     }
+///     fn visit_skipped(&mut self, _: BE) -> i32 { 0 }
+///     fn visit_failed_no_rollback(&mut self, _: E) -> i32 { 6 }
+///     fn visit_failed_no_rollback_but_failed_finally(&mut self, _: E, _: FE) -> i32 { 7 }
+/// }
+///
+/// type State = TransactionState<(), (), (), (), (), ()>;
+/// assert_eq!(0, State::Ok(()).accept(&mut ExitCode));
+/// assert_eq!(3, State::Rollback((), Err(())).accept(&mut ExitCode));
+/// ```
+pub trait TransactionStateVisitor<BE, O, E, RO, RE, FE> {
+    /// The result of visiting a state.
+    type Output;
+
+    /// Visits [`TransactionState::FailedBefore`].
+    fn visit_failed_before(&mut self, err: BE) -> Self::Output;
+    /// Visits [`TransactionState::Ok`].
+    fn visit_ok(&mut self, value: O) -> Self::Output;
+    /// Visits [`TransactionState::Rollback`].
+    fn visit_rollback(&mut self, err: E, rollback_result: Result<RO, RE>) -> Self::Output;
+    /// Visits [`TransactionState::OkButFailedFinally`].
+    fn visit_ok_but_failed_finally(&mut self, value: O, finally_err: FE) -> Self::Output;
+    /// Visits [`TransactionState::RollbackButFailedFinally`].
+    fn visit_rollback_but_failed_finally(
+        &mut self,
+        err: E,
+        rollback_result: Result<RO, RE>,
+        finally_err: FE,
+    ) -> Self::Output;
+    /// Visits [`TransactionState::Skipped`].
+    fn visit_skipped(&mut self, reason: BE) -> Self::Output;
+    /// Visits [`TransactionState::FailedNoRollback`].
+    fn visit_failed_no_rollback(&mut self, err: E) -> Self::Output;
+    /// Visits [`TransactionState::FailedNoRollbackButFailedFinally`].
+    fn visit_failed_no_rollback_but_failed_finally(
+        &mut self,
+        err: E,
+        finally_err: FE,
+    ) -> Self::Output;
+}
+
+/// A visitor for [`TransactionState`] that only handles some variants.
+///
+/// This works like [`TransactionStateVisitor`], but every method defaults to
+/// [`Self::visit_default`]. This way, a variant added in the future is handled by
+/// `visit_default` instead of requiring an update. Use [`TransactionState::accept_partial`]
+/// to visit a state with it.
+///
+/// ```rust
+/// use transaction_rollback::{PartialTransactionStateVisitor, TransactionState};
+///
+/// struct IsSuccess;
+///
+/// impl<BE, O, E, RO, RE, FE> PartialTransactionStateVisitor<BE, O, E, RO, RE, FE> for IsSuccess {
+///     type Output = bool;
+///
+///     fn visit_default(&mut self) -> bool {
+///         false
+///     }
+///
+///     fn visit_ok(&mut self, _: O) -> bool {
+///         true
+///     }
+/// }
+///
+/// type State = TransactionState<(), (), (), (), (), ()>;
+/// assert!(State::Ok(()).accept_partial(&mut IsSuccess));
+/// assert!(!State::OkButFailedFinally((), ()).accept_partial(&mut IsSuccess));
+/// ```
+pub trait PartialTransactionStateVisitor<BE, O, E, RO, RE, FE> {
+    /// The result of visiting a state.
+    type Output;
+
+    /// Visits all variants whose method is not implemented.
+    fn visit_default(&mut self) -> Self::Output;
+
+    /// Visits [`TransactionState::FailedBefore`].
+    fn visit_failed_before(&mut self, _err: BE) -> Self::Output {
+        self.visit_default()
+    }
+    /// Visits [`TransactionState::Ok`].
+    fn visit_ok(&mut self, _value: O) -> Self::Output {
+        self.visit_default()
+    }
+    /// Visits [`TransactionState::Rollback`].
+    fn visit_rollback(&mut self, _err: E, _rollback_result: Result<RO, RE>) -> Self::Output {
+        self.visit_default()
+    }
+    /// Visits [`TransactionState::OkButFailedFinally`].
+    fn visit_ok_but_failed_finally(&mut self, _value: O, _finally_err: FE) -> Self::Output {
+        self.visit_default()
+    }
+    /// Visits [`TransactionState::RollbackButFailedFinally`].
+    fn visit_rollback_but_failed_finally(
+        &mut self,
+        _err: E,
+        _rollback_result: Result<RO, RE>,
+        _finally_err: FE,
+    ) -> Self::Output {
+        self.visit_default()
+    }
+    /// Visits [`TransactionState::Skipped`].
+    fn visit_skipped(&mut self, _reason: BE) -> Self::Output {
+        self.visit_default()
+    }
+    /// Visits [`TransactionState::FailedNoRollback`].
+    fn visit_failed_no_rollback(&mut self, _err: E) -> Self::Output {
+        self.visit_default()
+    }
+    /// Visits [`TransactionState::FailedNoRollbackButFailedFinally`].
+    fn visit_failed_no_rollback_but_failed_finally(
+        &mut self,
+        _err: E,
+        _finally_err: FE,
+    ) -> Self::Output {
+        self.visit_default()
+    }
+}
+
+/// Adapts a [`PartialTransactionStateVisitor`] to a [`TransactionStateVisitor`].
+struct Partial<'v, V>(&'v mut V);
+
+impl<V, BE, O, E, RO, RE, FE> TransactionStateVisitor<BE, O, E, RO, RE, FE> for Partial<'_, V>
+where
+    V: PartialTransactionStateVisitor<BE, O, E, RO, RE, FE>,
+{
+    type Output = V::Output;
+
+    fn visit_failed_before(&mut self, err: BE) -> Self::Output {
+        self.0.visit_failed_before(err)
+    }
+
+    fn visit_ok(&mut self, value: O) -> Self::Output {
+        self.0.visit_ok(value)
+    }
+
+    fn visit_rollback(&mut self, err: E, rollback_result: Result<RO, RE>) -> Self::Output {
+        self.0.visit_rollback(err, rollback_result)
+    }
+
+    fn visit_ok_but_failed_finally(&mut self, value: O, finally_err: FE) -> Self::Output {
+        self.0.visit_ok_but_failed_finally(value, finally_err)
+    }
+
+    fn visit_rollback_but_failed_finally(
+        &mut self,
+        err: E,
+        rollback_result: Result<RO, RE>,
+        finally_err: FE,
+    ) -> Self::Output {
+        self.0
+            .visit_rollback_but_failed_finally(err, rollback_result, finally_err)
+    }
+
+    fn visit_skipped(&mut self, reason: BE) -> Self::Output {
+        self.0.visit_skipped(reason)
+    }
+
+    fn visit_failed_no_rollback(&mut self, err: E) -> Self::Output {
+        self.0.visit_failed_no_rollback(err)
+    }
+
+    fn visit_failed_no_rollback_but_failed_finally(
+        &mut self,
+        err: E,
+        finally_err: FE,
+    ) -> Self::Output {
+        self.0
+            .visit_failed_no_rollback_but_failed_finally(err, finally_err)
+    }
+}
+
+impl<BE, O, E, RO, RE, FE> TransactionState<BE, O, E, RO, RE, FE> {
+    /// Calls the method of `visitor` for the variant of this state, see
+    /// [`TransactionStateVisitor`].
+    pub fn accept<V>(self, visitor: &mut V) -> V::Output
+    where
+        V: TransactionStateVisitor<BE, O, E, RO, RE, FE>,
+    {
+        match self {
+            TransactionState::FailedBefore(be) => visitor.visit_failed_before(be),
+            TransactionState::Ok(o) => visitor.visit_ok(o),
+            TransactionState::Rollback(e, r) => visitor.visit_rollback(e, r),
+            TransactionState::OkButFailedFinally(o, fe) => {
+                visitor.visit_ok_but_failed_finally(o, fe)
+            }
+            TransactionState::RollbackButFailedFinally(e, r, fe) => {
+                visitor.visit_rollback_but_failed_finally(e, r, fe)
+            }
+            TransactionState::Skipped(be) => visitor.visit_skipped(be),
+            TransactionState::FailedNoRollback(e) => visitor.visit_failed_no_rollback(e),
+            TransactionState::FailedNoRollbackButFailedFinally(e, fe) => {
+                visitor.visit_failed_no_rollback_but_failed_finally(e, fe)
+            }
+        }
+    }
+
+    /// Calls the method of `visitor` for the variant of this state, see
+    /// [`PartialTransactionStateVisitor`].
+    pub fn accept_partial<V>(self, visitor: &mut V) -> V::Output
+    where
+        V: PartialTransactionStateVisitor<BE, O, E, RO, RE, FE>,
+    {
+        self.accept(&mut Partial(visitor))
+    }
+}